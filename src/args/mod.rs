@@ -19,6 +19,7 @@
 mod errors;
 
 use std::convert::TryFrom;
+use std::path::Path;
 
 use clap::App;
 use clap::Arg;
@@ -27,7 +28,21 @@ use log_derive::logfn;
 
 use self::errors::ParsingError;
 use super::commands::Command;
+use super::commands::OutputFormat;
+use super::commands::SourceType;
 use super::commands::StorageType;
+use super::config::config::Config;
+
+/// The option to select the output format of commands that display
+/// information.
+fn format_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("format")
+        .long("format")
+        .help("Output format")
+        .takes_value(true)
+        .possible_values(&["table", "json"])
+        .default_value("table")
+}
 
 /// Parse the command line, returning the necessary command.
 #[logfn(Trace)]
@@ -36,13 +51,47 @@ pub fn parse() -> Result<Command, ParsingError> {
         .version(clap::crate_version!())
         .author(clap::crate_authors!())
         .about(clap::crate_description!())
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .value_name("path")
+                .help("Use this configuration file instead of the default")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("downloads")
+                .long("downloads-per-host")
+                .value_name("count")
+                .help("How many attachments to download at the same time from the same server")
+                .takes_value(true)
+                .validator(|count| match count.parse::<usize>() {
+                    Ok(count) if count > 0 => Ok(()),
+                    _ => Err("must be a positive number".into()),
+                })
+                .global(true),
+        )
         .arg(
             Arg::with_name("account")
                 .help("Account alias")
                 .required(false),
         )
+        .subcommand(
+            SubCommand::with_name("accounts")
+                .about("Accounts management")
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("List all accounts")
+                        .arg(format_arg()),
+                ),
+        )
         .subcommand(SubCommand::with_name("create").about("Create the account"))
         .subcommand(SubCommand::with_name("remove").about("Remove the account"))
+        .subcommand(
+            SubCommand::with_name("show")
+                .about("Show the account information")
+                .arg(format_arg()),
+        )
         .subcommand(
             SubCommand::with_name("fetch")
                 .about("Fetch new favourites from this account only"),
@@ -51,34 +100,112 @@ pub fn parse() -> Result<Command, ParsingError> {
             SubCommand::with_name("sync")
                 .about("Sync the last seen favourite with the most recent one"),
         )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Import the toots from an archive exported by Mastodon")
+                .arg(
+                    Arg::with_name("archive")
+                        .help("The zip file with the archive")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("resolve")
+                        .long("resolve")
+                        .help("Fetch the toots the archive only has the address of from the server"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("source")
+                .about("Sources of toots to archive, besides favourites")
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Archive toots from a new source")
+                        .subcommand(
+                            SubCommand::with_name("bookmarks")
+                                .about("Archive the bookmarks of the account"),
+                        )
+                        .subcommand(
+                            SubCommand::with_name("statuses").about(
+                                "Archive the posts (and boosts) of the account",
+                            ),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("remove")
+                        .about("Stop archiving toots from a source")
+                        .subcommand(
+                            SubCommand::with_name("bookmarks")
+                                .about("Stop archiving bookmarks"),
+                        )
+                        .subcommand(
+                            SubCommand::with_name("statuses")
+                                .about("Stop archiving the account's own posts"),
+                        ),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("storage")
                 .about("Account storage")
                 .subcommand(
                     SubCommand::with_name("add")
                         .about("Add a new storage for the account")
+                        .arg(
+                            Arg::with_name("name")
+                                .long("name")
+                                .value_name("name")
+                                .help("Name of the storage; defaults to its type")
+                                .takes_value(true)
+                                .global(true),
+                        )
                         .subcommand(SubCommand::with_name("markdown").about(
                             "Store favourites on the filesystem, as markdown",
                         ))
                         .subcommand(SubCommand::with_name("org").about(
                             "Store favourites on the filesystem, as Org files",
+                        ))
+                        .subcommand(SubCommand::with_name("json").about(
+                            "Store the full favourites on the filesystem, as JSON",
+                        ))
+                        .subcommand(SubCommand::with_name("sqlite").about(
+                            "Store favourites in a SQLite database",
+                        ))
+                        .subcommand(SubCommand::with_name("html").about(
+                            "Store favourites as a static site, to be browsed",
+                        ))
+                        .subcommand(SubCommand::with_name("feed").about(
+                            "Keep the most recent favourites in an Atom or RSS feed",
                         )),
                 )
                 .subcommand(
                     SubCommand::with_name("remove")
                         .about("Remove a storage from the account")
-                        .subcommand(
-                            SubCommand::with_name("markdown")
-                                .about("Remove the markdown storage"),
-                        )
-                        .subcommand(
-                            SubCommand::with_name("org")
-                                .about("Remove the org storage"),
+                        .arg(
+                            Arg::with_name("name")
+                                .help("Name of the storage")
+                                .required(true),
                         ),
                 ),
         );
 
     let matches = parser.get_matches();
+    if let Some(config) = matches.value_of("config") {
+        Config::use_file(Path::new(config));
+    }
+    let downloads_per_host = matches
+        .value_of("downloads")
+        .and_then(|count| count.parse::<usize>().ok());
+
+    if let ("accounts", Some(args)) = matches.subcommand() {
+        return match args.subcommand() {
+            ("list", Some(list_args)) => {
+                Ok(Command::list_accounts(OutputFormat::try_from(
+                    list_args.value_of("format").unwrap_or("table"),
+                )?))
+            }
+            _ => Err(ParsingError::UnknownCommand),
+        };
+    }
+
     if let Some(account_name) = matches.value_of("account") {
         match matches.subcommand() {
             ("create", _) => Ok(Command::add_account(account_name.into())),
@@ -89,28 +216,68 @@ pub fn parse() -> Result<Command, ParsingError> {
                         .subcommand_name()
                         .ok_or(ParsingError::UnknownCommand)?;
                     log::debug!("Storage: {:?}", storage);
+                    let name = add_args
+                        .subcommand_matches(storage)
+                        .and_then(|type_args| type_args.value_of("name"))
+                        .unwrap_or(storage);
                     Ok(Command::add_storage(
                         account_name.into(),
                         StorageType::try_from(storage)?,
+                        name,
+                    ))
+                }
+                ("remove", Some(remove_args)) => Ok(Command::remove_storage(
+                    account_name.into(),
+                    remove_args
+                        .value_of("name")
+                        .ok_or(ParsingError::UnknownCommand)?,
+                )),
+                _ => Err(ParsingError::UnknownCommand),
+            },
+            ("source", Some(args)) => match args.subcommand() {
+                ("add", Some(add_args)) => {
+                    let source = add_args
+                        .subcommand_name()
+                        .ok_or(ParsingError::UnknownCommand)?;
+                    Ok(Command::add_source(
+                        account_name,
+                        SourceType::try_from(source)?,
                     ))
                 }
                 ("remove", Some(remove_args)) => {
-                    let storage = remove_args
+                    let source = remove_args
                         .subcommand_name()
                         .ok_or(ParsingError::UnknownCommand)?;
-                    Ok(Command::remove_storage(
-                        account_name.into(),
-                        StorageType::try_from(storage)?,
+                    Ok(Command::remove_source(
+                        account_name,
+                        SourceType::try_from(source)?,
                     ))
                 }
-                _ => unimplemented!(),
+                _ => Err(ParsingError::UnknownCommand),
             },
-            ("fetch", _) => Ok(Command::fetch(account_name.into())),
+            ("show", Some(args)) => Ok(Command::show_account(
+                account_name,
+                OutputFormat::try_from(
+                    args.value_of("format").unwrap_or("table"),
+                )?,
+            )),
+            ("fetch", _) => {
+                Ok(Command::fetch(account_name.into(), downloads_per_host))
+            }
             ("sync", _) => Ok(Command::sync(account_name.into())),
-            ("", _) => Ok(Command::fetch(account_name.into())),
+            ("import", Some(args)) => Ok(Command::import(
+                account_name,
+                args.value_of("archive")
+                    .ok_or(ParsingError::UnknownCommand)?,
+                args.is_present("resolve"),
+                downloads_per_host,
+            )),
+            ("", _) => {
+                Ok(Command::fetch(account_name.into(), downloads_per_host))
+            }
             _ => Err(ParsingError::UnknownCommand),
         }
     } else {
-        Ok(Command::fetch_all())
+        Ok(Command::fetch_all(downloads_per_host))
     }
 }
@@ -0,0 +1,213 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::VecDeque;
+
+use reqwest::header::LINK;
+use reqwest::Client;
+use reqwest::RequestBuilder;
+use reqwest::Response;
+use serde_json::Value;
+
+use super::errors::CommandError;
+use crate::storage::data::Toot;
+
+/// The parts of the Mastodon API used to list toots.
+///
+/// Elefren only gives back the statuses it parsed, without the fields it
+/// doesn't know about, so we call the API directly to keep the JSON the
+/// server sent. Elefren doesn't support the bookmarks at all.
+pub struct Api {
+    client: Client,
+    base: String,
+    token: String,
+}
+
+impl Api {
+    pub fn new(data: &elefren::Data) -> Self {
+        Self {
+            client: Client::new(),
+            base: data.base.trim_end_matches('/').into(),
+            token: data.token.to_string(),
+        }
+    }
+
+    /// The favourites of the account, most recent first.
+    pub fn favourites(&self) -> Result<Pages<'_>, CommandError> {
+        Pages::new(self, &format!("{}/api/v1/favourites", self.base))
+    }
+
+    /// The bookmarks of the account, most recent first.
+    pub fn bookmarks(&self) -> Result<Pages<'_>, CommandError> {
+        Pages::new(self, &format!("{}/api/v1/bookmarks", self.base))
+    }
+
+    /// The statuses posted (and boosted) by the account with the ID, most
+    /// recent first.
+    pub fn statuses(
+        &self,
+        id: &str,
+        replies: bool,
+        reblogs: bool,
+    ) -> Result<Pages<'_>, CommandError> {
+        let mut url = format!("{}/api/v1/accounts/{}/statuses", self.base, id);
+        let mut exclude = Vec::new();
+        if !replies {
+            exclude.push("exclude_replies=true");
+        }
+        if !reblogs {
+            exclude.push("exclude_reblogs=true");
+        }
+        if !exclude.is_empty() {
+            url.push('?');
+            url.push_str(&exclude.join("&"));
+        }
+        Pages::new(self, &url)
+    }
+
+    /// The toots the status replies to, oldest first, and the replies to it.
+    pub fn context(
+        &self,
+        id: &str,
+    ) -> Result<(Vec<Toot>, Vec<Toot>), CommandError> {
+        let mut context: Value = self
+            .get(&format!("{}/api/v1/statuses/{}/context", self.base, id))?
+            .json()?;
+        Ok((
+            toots(context["ancestors"].take()),
+            toots(context["descendants"].take()),
+        ))
+    }
+
+    /// Find the toot with the ActivityPub address, asking the server to
+    /// fetch it from its origin if it doesn't know it yet.
+    pub fn resolve(&self, uri: &str) -> Result<Option<Toot>, CommandError> {
+        log::debug!("Resolving {}", uri);
+        let request = self
+            .client
+            .get(&format!("{}/api/v2/search", self.base))
+            .query(&[
+                ("q", uri),
+                ("type", "statuses"),
+                ("resolve", "true"),
+                ("limit", "1"),
+            ]);
+        let mut results: Value = self.send(request)?.json()?;
+        Ok(toots(results["statuses"].take()).into_iter().next())
+    }
+
+    fn get(&self, url: &str) -> Result<Response, CommandError> {
+        log::debug!("Loading {}", url);
+        self.send(self.client.get(url))
+    }
+
+    fn send(&self, request: RequestBuilder) -> Result<Response, CommandError> {
+        Ok(request
+            .bearer_auth(&self.token)
+            .send()?
+            .error_for_status()?)
+    }
+}
+
+/// A list of toots, loaded one page at a time, following the pages in the
+/// `Link` header.
+///
+/// A page that fails to load is returned as an error, so it isn't taken as
+/// the end of the list.
+pub struct Pages<'a> {
+    api: &'a Api,
+    items: VecDeque<Toot>,
+    next: Option<String>,
+}
+
+impl<'a> Pages<'a> {
+    /// Retrieve the first page of the list.
+    fn new(api: &'a Api, url: &str) -> Result<Self, CommandError> {
+        let mut pages = Self {
+            api,
+            items: VecDeque::new(),
+            next: None,
+        };
+        pages.load(url)?;
+        Ok(pages)
+    }
+
+    /// Load one page of the list.
+    fn load(&mut self, url: &str) -> Result<(), CommandError> {
+        let mut response = self.api.get(url)?;
+        self.next = next_page(&response);
+        self.items.extend(toots(response.json()?));
+        Ok(())
+    }
+}
+
+impl Iterator for Pages<'_> {
+    type Item = Result<Toot, CommandError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // A page may have nothing we can use, but still not be the last one.
+        while self.items.is_empty() {
+            let url = self.next.take()?;
+            if let Err(e) = self.load(&url) {
+                return Some(Err(e));
+            }
+        }
+        self.items.pop_front().map(Ok)
+    }
+}
+
+/// Parse a list of statuses; a status that can't be parsed is reported and
+/// skipped, so it doesn't keep the rest of the list from being archived.
+fn toots(list: Value) -> Vec<Toot> {
+    match list {
+        Value::Array(statuses) => statuses
+            .into_iter()
+            .filter_map(|json| {
+                let id = json["id"].as_str().unwrap_or("?").to_string();
+                match Toot::parse(json) {
+                    Ok(toot) => Some(toot),
+                    Err(e) => {
+                        println!(
+                            "Skipping status {}, can't read it: {}",
+                            id, e
+                        );
+                        None
+                    }
+                }
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// The URL of the next page, from a `Link` header like
+/// `<https://server/api/v1/bookmarks?max_id=1>; rel="next", <...>; rel="prev"`.
+fn next_page(response: &Response) -> Option<String> {
+    let header = response.headers().get(LINK)?.to_str().ok()?;
+    header.split(',').find_map(|link| {
+        let mut parts = link.split(';');
+        let url = parts.next()?.trim();
+        let is_next =
+            parts.any(|param| param.trim().replace('"', "") == "rel=next");
+        if is_next && url.starts_with('<') && url.ends_with('>') {
+            Some(url[1..url.len() - 1].to_string())
+        } else {
+            None
+        }
+    })
+}
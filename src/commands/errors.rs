@@ -17,6 +17,7 @@
 */
 
 use crate::config::errors::ConfigError;
+use crate::storage::errors::StorageError;
 
 /// Errors for the commands
 #[derive(Debug)]
@@ -29,6 +30,30 @@ pub enum CommandError {
 
     /// The storage type requested does not exist
     NoSuchStorage,
+
+    /// The account has no storage with this name
+    NoSuchStorageName(String),
+
+    /// The account already has a storage with this name
+    DuplicateStorage(String),
+
+    /// The source requested does not exist
+    NoSuchSource,
+
+    /// The account alias is not in the configuration
+    NoSuchAccount(String),
+
+    /// The output format requested does not exist
+    NoSuchFormat,
+
+    /// The output couldn't be produced
+    OutputError,
+
+    /// The storage couldn't be used
+    StorageError(StorageError),
+
+    /// The export archive couldn't be read
+    ArchiveError,
 }
 
 impl From<elefren::Error> for CommandError {
@@ -37,8 +62,35 @@ impl From<elefren::Error> for CommandError {
     }
 }
 
+impl From<reqwest::Error> for CommandError {
+    fn from(e: reqwest::Error) -> CommandError {
+        log::debug!("Request error: {:?}", e);
+        CommandError::ConnectError
+    }
+}
+
 impl From<ConfigError> for CommandError {
     fn from(e: ConfigError) -> CommandError {
         CommandError::ConfigError(e)
     }
 }
+
+impl From<serde_json::Error> for CommandError {
+    fn from(e: serde_json::Error) -> CommandError {
+        log::debug!("JSON error: {:?}", e);
+        CommandError::OutputError
+    }
+}
+
+impl From<StorageError> for CommandError {
+    fn from(e: StorageError) -> CommandError {
+        CommandError::StorageError(e)
+    }
+}
+
+impl From<zip::result::ZipError> for CommandError {
+    fn from(e: zip::result::ZipError) -> CommandError {
+        log::debug!("Archive error: {:?}", e);
+        CommandError::ArchiveError
+    }
+}
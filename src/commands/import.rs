@@ -0,0 +1,476 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use serde_json::json;
+use serde_json::Value;
+use zip::read::ZipFile;
+use zip::ZipArchive;
+
+use super::errors::CommandError;
+use crate::storage::data::Origin;
+use crate::storage::data::Toot;
+
+/// The address used by ActivityPub for "everyone".
+const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+/// The archive produced by "Request your archive", in the Mastodon settings.
+///
+/// The archive has the account (`actor.json`), everything it posted or
+/// boosted (`outbox.json`), its favourites (`likes.json`) and bookmarks
+/// (`bookmarks.json`), all in ActivityPub format, and the attachments of its
+/// posts (`media_attachments/`). The toots are converted to the format used
+/// by the API, so they can go through the same storages as the fetched ones.
+pub struct Export {
+    zip: ZipArchive<File>,
+    /// The server of the account, used to build the address of attachments
+    /// that are inside the archive.
+    base: String,
+    /// The account, in the format used by the API.
+    actor: Value,
+    /// The toots posted by the account, by their ActivityPub ID.
+    notes: HashMap<String, Value>,
+}
+
+/// A toot from the archive, ready to be archived.
+pub struct Imported {
+    /// The ID used to track the toot as archived; for toots from other
+    /// servers, this is their address, which can't be mistaken for the ID of
+    /// a toot in ours.
+    pub id: String,
+    /// The ActivityPub address of the toot.
+    pub uri: String,
+    /// The toot, unless the archive only has its address.
+    pub status: Option<Toot>,
+    pub origin: Origin,
+}
+
+impl Export {
+    /// Open the archive of an account in the server.
+    pub fn open(path: &Path, base: &str) -> Result<Self, CommandError> {
+        let mut zip = ZipArchive::new(File::open(path).map_err(|e| {
+            log::debug!("Can't open {:?}: {:?}", path, e);
+            CommandError::ArchiveError
+        })?)?;
+        let actor = read_json(&mut zip, "actor.json")?;
+        let mut export = Self {
+            zip,
+            base: base.trim_end_matches('/').into(),
+            actor: Value::Null,
+            notes: HashMap::new(),
+        };
+        export.actor = export.account(&actor, "");
+        Ok(export)
+    }
+
+    /// The toots posted and boosted by the account, oldest first.
+    pub fn outbox(&mut self) -> Result<Vec<Imported>, CommandError> {
+        let activities = items(read_json(&mut self.zip, "outbox.json")?);
+        for activity in activities.iter() {
+            if let (Some("Create"), Some(note)) =
+                (activity["type"].as_str(), activity["object"].as_object())
+            {
+                if let Some(id) = note.get("id").and_then(Value::as_str) {
+                    self.notes.insert(id.into(), Value::from(note.clone()));
+                }
+            }
+        }
+
+        let mut imported = Vec::new();
+        for activity in activities.iter() {
+            let id = activity["id"].as_str().unwrap_or_default();
+            let id = status_id(id);
+            match activity["type"].as_str() {
+                Some("Create") => imported.push(self.import(
+                    &id,
+                    &activity["object"],
+                    Origin::Post,
+                )?),
+                // Only the address of a boosted toot is in the archive; we
+                // have the toot only if the account posted it.
+                Some("Announce") => {
+                    let address =
+                        activity["object"].as_str().unwrap_or_default();
+                    imported.push(match self.notes.get(address) {
+                        Some(note) => self.import(&id, note, Origin::Boost)?,
+                        None => Imported::address(&id, address, Origin::Boost),
+                    });
+                }
+                _ => continue,
+            }
+        }
+        Ok(imported)
+    }
+
+    /// The toots favourited by the account.
+    pub fn likes(&mut self) -> Result<Vec<Imported>, CommandError> {
+        self.collection("likes.json", Origin::Favourite)
+    }
+
+    /// The toots bookmarked by the account.
+    pub fn bookmarks(&mut self) -> Result<Vec<Imported>, CommandError> {
+        self.collection("bookmarks.json", Origin::Bookmark)
+    }
+
+    /// The file in the archive with the content of an attachment, if the
+    /// attachment came from the archive.
+    pub fn media(
+        &mut self,
+        url: &str,
+    ) -> Result<Option<ZipFile<'_>>, CommandError> {
+        let prefix = format!("{}/", self.base);
+        match url.strip_prefix(&prefix) {
+            Some(name) if self.zip.file_names().any(|file| file == name) => {
+                Ok(Some(self.zip.by_name(name)?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// A list of toots, which may be only their addresses (which is what
+    /// Mastodon exports) or the toots themselves.
+    fn collection(
+        &mut self,
+        name: &str,
+        origin: Origin,
+    ) -> Result<Vec<Imported>, CommandError> {
+        // The collections are optional: older archives don't have bookmarks.
+        if self.zip.by_name(name).is_err() {
+            return Ok(Vec::new());
+        }
+
+        let mut imported = Vec::new();
+        for item in items(read_json(&mut self.zip, name)?) {
+            let note = match &item {
+                Value::String(address) => match self.notes.get(address) {
+                    Some(note) => note.clone(),
+                    None => {
+                        imported.push(Imported::address(
+                            &self.local_id(address),
+                            address,
+                            origin,
+                        ));
+                        continue;
+                    }
+                },
+                Value::Object(_) => item,
+                _ => continue,
+            };
+            let id = self.local_id(note["id"].as_str().unwrap_or_default());
+            imported.push(self.import(&id, &note, origin)?);
+        }
+        Ok(imported)
+    }
+
+    /// The ID used to track the toot with the address as archived.
+    ///
+    /// The ID in the address of a toot from another server is the one used
+    /// by that server, which could be the ID of some other toot in ours, so
+    /// the address itself is used (until the toot is resolved).
+    fn local_id(&self, address: &str) -> String {
+        let host = |url: &str| {
+            reqwest::Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().map(String::from))
+        };
+        match host(address) {
+            Some(server) if Some(&server) == host(&self.base).as_ref() => {
+                status_id(address)
+            }
+            _ => address.to_string(),
+        }
+    }
+
+    /// Convert an ActivityPub note to a toot, as returned by the API.
+    fn import(
+        &self,
+        id: &str,
+        note: &Value,
+        origin: Origin,
+    ) -> Result<Imported, CommandError> {
+        let uri = note["id"].as_str().unwrap_or_default();
+        let published = note["published"].as_str().unwrap_or_default();
+        let author = note["attributedTo"].as_str().unwrap_or_default();
+        let account = if Some(author) == self.actor["url"].as_str()
+            || Some(author) == self.actor["uri"].as_str()
+        {
+            self.actor.clone()
+        } else {
+            self.account(&json!({ "id": author }), published)
+        };
+
+        let id_in_server = status_id(uri);
+        let status = json!({
+            "id": id_in_server,
+            "uri": uri,
+            "url": note["url"].as_str().unwrap_or(uri),
+            "account": account,
+            "in_reply_to_id": note["inReplyTo"].as_str().map(status_id),
+            "in_reply_to_account_id": null,
+            "reblog": null,
+            "content": note["content"].as_str().unwrap_or_default(),
+            "created_at": published,
+            "emojis": [],
+            "reblogs_count": 0,
+            "favourites_count": 0,
+            "sensitive": note["sensitive"].as_bool().unwrap_or(false),
+            "spoiler_text": note["summary"].as_str().unwrap_or_default(),
+            "visibility": visibility(note),
+            "media_attachments": list(&note["attachment"])
+                .iter()
+                .enumerate()
+                .map(|(position, attachment)| {
+                    self.attachment(&id_in_server, position, attachment)
+                })
+                .collect::<Vec<Value>>(),
+            "mentions": tags(note, "Mention")
+                .map(|tag| {
+                    let acct = tag["name"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .trim_start_matches('@');
+                    json!({
+                        "url": tag["href"].as_str().unwrap_or_default(),
+                        "username": acct.split('@').next().unwrap_or_default(),
+                        "acct": acct,
+                        "id": "",
+                    })
+                })
+                .collect::<Vec<Value>>(),
+            "tags": tags(note, "Hashtag")
+                .map(|tag| json!({
+                    "name": tag["name"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .trim_start_matches('#'),
+                    "url": tag["href"].as_str().unwrap_or_default(),
+                }))
+                .collect::<Vec<Value>>(),
+            "language": note["contentMap"]
+                .as_object()
+                .and_then(|languages| languages.keys().next()),
+        });
+        let status = Toot::parse(status).map_err(|e| {
+            log::debug!("Can't convert {}: {:?}", uri, e);
+            CommandError::ArchiveError
+        })?;
+        Ok(Imported {
+            id: id.into(),
+            uri: uri.into(),
+            status: Some(status),
+            origin,
+        })
+    }
+
+    /// Convert an ActivityPub actor to an account, as returned by the API.
+    ///
+    /// The archive only has the details of the account itself; for anyone
+    /// else, all we know is the address (`https://server/users/name`).
+    fn account(&self, actor: &Value, created_at: &str) -> Value {
+        let address = actor["id"].as_str().unwrap_or_default();
+        let url = actor["url"].as_str().unwrap_or(address);
+        let username = match actor["preferredUsername"].as_str() {
+            Some(username) => username.to_string(),
+            None => address
+                .rsplit('/')
+                .next()
+                .unwrap_or_default()
+                .trim_start_matches('@')
+                .to_string(),
+        };
+        let host = reqwest::Url::parse(address)
+            .ok()
+            .and_then(|address| address.host_str().map(String::from));
+        let base_host = reqwest::Url::parse(&self.base)
+            .ok()
+            .and_then(|base| base.host_str().map(String::from));
+        // Like the API, local accounts don't have the server in their name.
+        let acct = match host {
+            Some(host) if Some(&host) != base_host.as_ref() => {
+                format!("{}@{}", username, host)
+            }
+            _ => username.to_string(),
+        };
+        let created_at = actor["published"].as_str().unwrap_or(created_at);
+        json!({
+            "acct": acct,
+            "avatar": "",
+            "avatar_static": "",
+            "created_at": if created_at.is_empty() {
+                "1970-01-01T00:00:00Z"
+            } else {
+                created_at
+            },
+            "display_name": actor["name"].as_str().unwrap_or(&username),
+            "followers_count": 0,
+            "following_count": 0,
+            "header": "",
+            "header_static": "",
+            "id": "",
+            "locked": actor["manuallyApprovesFollowers"]
+                .as_bool()
+                .unwrap_or(false),
+            "note": actor["summary"].as_str().unwrap_or_default(),
+            "statuses_count": 0,
+            "url": url,
+            "uri": address,
+            "username": username,
+        })
+    }
+
+    /// Convert an ActivityPub document to an attachment, as returned by the
+    /// API. Attachments inside the archive are addressed as if they were in
+    /// the server, so they are found by `media`.
+    ///
+    /// The archive doesn't have the IDs of the attachments, so they are
+    /// numbered inside the toot.
+    fn attachment(
+        &self,
+        status: &str,
+        position: usize,
+        document: &Value,
+    ) -> Value {
+        let url = document["url"].as_str().unwrap_or_default();
+        let url = if url.contains("://") {
+            url.to_string()
+        } else {
+            format!(
+                "{}/{}",
+                self.base,
+                url.trim_start_matches('/').trim_start_matches("system/")
+            )
+        };
+        let media_type = document["mediaType"].as_str().unwrap_or_default();
+        let media_type = if media_type.starts_with("image/") {
+            "image"
+        } else if media_type.starts_with("video/") {
+            "video"
+        } else {
+            "unknown"
+        };
+        let meta =
+            match (document["width"].as_u64(), document["height"].as_u64()) {
+                (Some(width), Some(height)) => json!({
+                    "original": { "width": width, "height": height }
+                }),
+                _ => Value::Null,
+            };
+        json!({
+            "id": format!("{}-{}", status, position),
+            "type": media_type,
+            "url": url,
+            "preview_url": url,
+            "meta": meta,
+            "description": document["name"].as_str(),
+        })
+    }
+}
+
+impl Imported {
+    /// A toot the archive only has the address of.
+    fn address(id: &str, uri: &str, origin: Origin) -> Self {
+        Self {
+            id: id.into(),
+            uri: uri.into(),
+            status: None,
+            origin,
+        }
+    }
+}
+
+/// Read and parse a JSON file from the archive.
+fn read_json(
+    zip: &mut ZipArchive<File>,
+    name: &str,
+) -> Result<Value, CommandError> {
+    let mut content = String::new();
+    zip.by_name(name)?
+        .read_to_string(&mut content)
+        .map_err(|e| {
+            log::debug!("Can't read {}: {:?}", name, e);
+            CommandError::ArchiveError
+        })?;
+    serde_json::from_str(&content).map_err(|e| {
+        log::debug!("Can't parse {}: {:?}", name, e);
+        CommandError::ArchiveError
+    })
+}
+
+/// The items of an ActivityPub collection.
+fn items(collection: Value) -> Vec<Value> {
+    match collection {
+        Value::Object(mut collection) => {
+            match collection.remove("orderedItems") {
+                Some(Value::Array(items)) => items,
+                _ => Vec::new(),
+            }
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// A field that can be a single value or a list of them, as a list.
+fn list(field: &Value) -> Vec<&Value> {
+    match field {
+        Value::Array(values) => values.iter().collect(),
+        Value::Null => Vec::new(),
+        value => vec![value],
+    }
+}
+
+/// The tags of some type (hashtags, mentions...) of a note.
+fn tags<'a>(note: &'a Value, kind: &'a str) -> impl Iterator<Item = &'a Value> {
+    list(&note["tag"])
+        .into_iter()
+        .filter(move |tag| tag["type"].as_str() == Some(kind))
+}
+
+/// The ID of a toot, from its ActivityPub address; for Mastodon, this is the
+/// same ID used by the API (`https://server/users/name/statuses/ID`).
+fn status_id(address: &str) -> String {
+    address
+        .trim_end_matches('/')
+        .trim_end_matches("/activity")
+        .rsplit('/')
+        .next()
+        .unwrap_or(address)
+        .to_string()
+}
+
+/// Who can see the toot, based on who it was addressed to.
+fn visibility(note: &Value) -> &'static str {
+    let addressed = |field: &str, who: &dyn Fn(&str) -> bool| {
+        list(&note[field])
+            .iter()
+            .filter_map(|address| address.as_str())
+            .any(|address| who(address))
+    };
+    if addressed("to", &|address| address == PUBLIC) {
+        "public"
+    } else if addressed("cc", &|address| address == PUBLIC) {
+        "unlisted"
+    } else if addressed("to", &|address| address.ends_with("/followers")) {
+        "private"
+    } else {
+        "direct"
+    }
+}
@@ -16,29 +16,54 @@
    along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+mod api;
 pub mod errors;
+mod import;
+mod storages;
+mod summary;
 
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::io;
 use std::io::prelude::*;
+use std::path::Path;
+use std::rc::Rc;
 
 use elefren::helpers::cli;
 use elefren::prelude::*;
 
+use self::api::Api;
+use self::api::Pages;
 use self::errors::CommandError;
+use self::import::Export;
+use self::storages::Storages;
+use self::summary::AccountSummary;
 use crate::config::account::AccountConfig;
 use crate::config::config::Config;
+use crate::config::filter::Filter;
+use crate::config::seen::Seen;
+use crate::config::statuses::StatusesConfig;
+use crate::config::storage::StorageConfig;
+use crate::config::storage::StorageEntry;
 use crate::config::Configurable;
 use crate::storage::data::Data;
+use crate::storage::data::Origin;
+use crate::storage::data::Toot;
+use crate::storage::feed::config::FeedConfig;
+use crate::storage::html::config::HtmlConfig;
+use crate::storage::json::config::JsonConfig;
 use crate::storage::markdown::config::MarkdownConfig;
-use crate::storage::markdown::storage::Markdown;
 use crate::storage::org::config::OrgConfig;
-use crate::storage::org::storage::Org;
-use crate::storage::storage::Storage;
+use crate::storage::pool::DEFAULT_PER_HOST;
+use crate::storage::sqlite::config::SqliteConfig;
+use crate::storage::store::AttachmentStore;
 
 type CommandResult = Result<(), CommandError>;
 
+/// How many already archived favourites, in a row, we need to find before
+/// assuming there is nothing new.
+const KNOWN_IN_A_ROW_LIMIT: usize = 20;
+
 /// Available Storages.
 #[derive(Debug)]
 pub enum StorageType {
@@ -47,6 +72,18 @@ pub enum StorageType {
 
     /// Store in the filesystem, as Org-Mode.
     Org,
+
+    /// Store in the filesystem, as JSON.
+    Json,
+
+    /// Store in a SQLite database.
+    Sqlite,
+
+    /// Store in the filesystem, as a static site.
+    Html,
+
+    /// Store the most recent toots in an Atom or RSS feed.
+    Feed,
 }
 
 impl TryFrom<&str> for StorageType {
@@ -56,11 +93,59 @@ impl TryFrom<&str> for StorageType {
         match s {
             "markdown" => Ok(StorageType::Markdown),
             "org" => Ok(StorageType::Org),
+            "json" => Ok(StorageType::Json),
+            "sqlite" => Ok(StorageType::Sqlite),
+            "html" => Ok(StorageType::Html),
+            "feed" => Ok(StorageType::Feed),
             _ => Err(Self::Error::NoSuchStorage),
         }
     }
 }
 
+/// Available sources of toots, besides favourites.
+#[derive(Debug)]
+pub enum SourceType {
+    /// The bookmarks of the account.
+    Bookmarks,
+
+    /// The statuses posted (and, optionally, boosted) by the account.
+    Statuses,
+}
+
+impl TryFrom<&str> for SourceType {
+    type Error = errors::CommandError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s {
+            "bookmarks" => Ok(SourceType::Bookmarks),
+            "statuses" => Ok(SourceType::Statuses),
+            _ => Err(Self::Error::NoSuchSource),
+        }
+    }
+}
+
+/// Output formats for the commands that display information.
+#[derive(Debug)]
+pub enum OutputFormat {
+    /// Human readable.
+    Table,
+
+    /// JSON, for scripts.
+    Json,
+}
+
+impl TryFrom<&str> for OutputFormat {
+    type Error = errors::CommandError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(Self::Error::NoSuchFormat),
+        }
+    }
+}
+
 /// Available commands.
 #[derive(Debug)]
 pub enum Command {
@@ -70,20 +155,38 @@ pub enum Command {
     /// Remove an account.
     RemoveAccount(String),
 
-    /// Add a storage in an account.
-    AddStorage(String, StorageType),
+    /// Add a named storage in an account.
+    AddStorage(String, StorageType, String),
 
-    /// Remove a storage in an account.
-    RemoveStorage(String, StorageType),
+    /// Remove a storage, by name, in an account.
+    RemoveStorage(String, String),
 
-    /// Fetch favourites from all accounts.
-    FetchAll,
+    /// Start archiving toots from another source in an account.
+    AddSource(String, SourceType),
 
-    /// Fetch one single account.
-    Fetch(String),
+    /// Stop archiving toots from a source in an account.
+    RemoveSource(String, SourceType),
+
+    /// Fetch favourites from all accounts, with an optional limit of
+    /// simultaneous downloads per server.
+    FetchAll(Option<usize>),
+
+    /// Fetch one single account, with an optional limit of simultaneous
+    /// downloads per server.
+    Fetch(String, Option<usize>),
 
     /// Forces the last favourite to be the current favourite.
     Sync(String),
+
+    /// Import the toots from an archive exported by Mastodon into an account,
+    /// optionally fetching the ones the archive only has the address of.
+    Import(String, String, bool, Option<usize>),
+
+    /// List all accounts.
+    ListAccounts(OutputFormat),
+
+    /// Display the information about one account.
+    ShowAccount(String, OutputFormat),
 }
 
 impl Command {
@@ -95,40 +198,89 @@ impl Command {
         Command::RemoveAccount(name.into())
     }
 
-    pub fn add_storage(account: &str, storage: StorageType) -> Self {
-        Command::AddStorage(account.into(), storage)
+    pub fn add_storage(
+        account: &str,
+        storage: StorageType,
+        name: &str,
+    ) -> Self {
+        Command::AddStorage(account.into(), storage, name.into())
+    }
+
+    pub fn remove_storage(account: &str, name: &str) -> Self {
+        Command::RemoveStorage(account.into(), name.into())
+    }
+
+    pub fn add_source(account: &str, source: SourceType) -> Self {
+        Command::AddSource(account.into(), source)
     }
 
-    pub fn remove_storage(account: &str, storage: StorageType) -> Self {
-        Command::RemoveStorage(account.into(), storage)
+    pub fn remove_source(account: &str, source: SourceType) -> Self {
+        Command::RemoveSource(account.into(), source)
     }
 
-    pub fn fetch_all() -> Self {
-        Command::FetchAll
+    pub fn fetch_all(downloads_per_host: Option<usize>) -> Self {
+        Command::FetchAll(downloads_per_host)
     }
 
-    pub fn fetch(account: &str) -> Self {
-        Command::Fetch(account.into())
+    pub fn fetch(account: &str, downloads_per_host: Option<usize>) -> Self {
+        Command::Fetch(account.into(), downloads_per_host)
     }
 
     pub fn sync(account: &str) -> Self {
         Command::Sync(account.into())
     }
 
+    pub fn import(
+        account: &str,
+        archive: &str,
+        resolve: bool,
+        downloads_per_host: Option<usize>,
+    ) -> Self {
+        Command::Import(
+            account.into(),
+            archive.into(),
+            resolve,
+            downloads_per_host,
+        )
+    }
+
+    pub fn list_accounts(format: OutputFormat) -> Self {
+        Command::ListAccounts(format)
+    }
+
+    pub fn show_account(account: &str, format: OutputFormat) -> Self {
+        Command::ShowAccount(account.into(), format)
+    }
+
     /// Execute the command, based on its value
     pub fn execute(&self) -> CommandResult {
         match self {
             Command::AddAccount(name) => add_account(name),
             Command::RemoveAccount(name) => remove_account(name),
-            Command::AddStorage(account, storage) => {
-                add_storage(account, storage)
+            Command::AddStorage(account, storage, name) => {
+                add_storage(account, storage, name)
+            }
+            Command::RemoveStorage(account, name) => {
+                remove_storage(account, name)
+            }
+            Command::AddSource(account, source) => add_source(account, source),
+            Command::RemoveSource(account, source) => {
+                remove_source(account, source)
             }
-            Command::RemoveStorage(account, storage) => {
-                remove_storage(account, storage)
+            Command::FetchAll(downloads_per_host) => {
+                fetch_all(*downloads_per_host)
+            }
+            Command::Fetch(account, downloads_per_host) => {
+                fetch_account(account, *downloads_per_host)
             }
-            Command::FetchAll => fetch_all(),
-            Command::Fetch(account) => fetch_account(account),
             Command::Sync(account) => sync_account(account),
+            Command::Import(account, archive, resolve, downloads_per_host) => {
+                import_archive(account, archive, *resolve, *downloads_per_host)
+            }
+            Command::ListAccounts(format) => list_accounts(format),
+            Command::ShowAccount(account, format) => {
+                show_account(account, format)
+            }
         }
     }
 }
@@ -159,102 +311,597 @@ fn remove_account(name: &str) -> CommandResult {
     Ok(())
 }
 
-fn add_storage(account: &str, storage: &StorageType) -> CommandResult {
+fn add_storage(
+    account: &str,
+    storage: &StorageType,
+    name: &str,
+) -> CommandResult {
     let mut config = Config::open()?;
-    match storage {
+    let account_config = config
+        .account(account)
+        .ok_or_else(|| CommandError::NoSuchAccount(account.into()))?;
+    if account_config
+        .storages()
+        .iter()
+        .any(|storage| storage.name == name)
+    {
+        return Err(CommandError::DuplicateStorage(name.into()));
+    }
+
+    let storage_config = match storage {
         StorageType::Markdown => {
-            let storage_config = MarkdownConfig::config()?;
-            config.set_storage_markdown(account, storage_config);
-        }
-        StorageType::Org => {
-            let storage_config = OrgConfig::config()?;
-            config.set_storage_org(account, storage_config);
+            StorageConfig::Markdown(MarkdownConfig::config()?)
         }
+        StorageType::Org => StorageConfig::Org(OrgConfig::config()?),
+        StorageType::Json => StorageConfig::Json(JsonConfig::config()?),
+        StorageType::Sqlite => StorageConfig::Sqlite(SqliteConfig::config()?),
+        StorageType::Html => StorageConfig::Html(HtmlConfig::config()?),
+        StorageType::Feed => StorageConfig::Feed(FeedConfig::config()?),
+    };
+    let entry = StorageEntry {
+        name: name.into(),
+        config: storage_config,
+        filter: Filter::default(),
+    };
+    if !config.add_storage(account, entry) {
+        return Err(CommandError::DuplicateStorage(name.into()));
     }
     config.save()?;
     Ok(())
 }
 
-fn remove_storage(account: &str, storage: &StorageType) -> CommandResult {
+fn remove_storage(account: &str, name: &str) -> CommandResult {
     let mut config = Config::open()?;
-    match storage {
-        StorageType::Markdown => {
-            config.remove_storage_markdown(account);
-        }
-        StorageType::Org => {
-            config.remove_storage_org(account);
+    if !config.remove_storage(account, name) {
+        return Err(CommandError::NoSuchStorageName(name.into()));
+    }
+    config.save()?;
+    Ok(())
+}
+
+fn add_source(account: &str, source: &SourceType) -> CommandResult {
+    let mut config = Config::open()?;
+    match source {
+        SourceType::Bookmarks => config.enable_bookmarks(account),
+        SourceType::Statuses => {
+            let statuses = StatusesConfig::config()?;
+            config.set_statuses(account, statuses);
         }
     }
     config.save()?;
     Ok(())
 }
 
-fn fetch_all() -> CommandResult {
+fn remove_source(account: &str, source: &SourceType) -> CommandResult {
+    let mut config = Config::open()?;
+    match source {
+        SourceType::Bookmarks => config.disable_bookmarks(account),
+        SourceType::Statuses => config.remove_statuses(account),
+    }
+    config.save()?;
+    Ok(())
+}
+
+/// The most recent toots archived from each source of an account.
+#[derive(Debug, Default)]
+struct Checkpoints {
+    favourite: Option<String>,
+    bookmark: Option<String>,
+    status: Option<String>,
+}
+
+impl Checkpoints {
+    /// Update the account configuration with the new checkpoints, returning
+    /// whether anything changed.
+    fn apply(&self, config: &mut Config, account: &str) -> bool {
+        if let Some(favourite) = &self.favourite {
+            config.set_new_favourite(account, favourite);
+        }
+        if let Some(bookmark) = &self.bookmark {
+            config.set_new_bookmark(account, bookmark);
+        }
+        if let Some(status) = &self.status {
+            config.set_new_status(account, status);
+        }
+        self.favourite.is_some()
+            || self.bookmark.is_some()
+            || self.status.is_some()
+    }
+}
+
+fn fetch_all(downloads_per_host: Option<usize>) -> CommandResult {
     // So, retrieve the favourites and get the latest seen...
     let config = Config::open()?;
-    let mut favourites: HashMap<String, String> = HashMap::new();
+    let mut checkpoints: HashMap<String, Checkpoints> = HashMap::new();
     for (name, account_config) in config.into_iter() {
         log::debug!("Fetching new items from {:?}", name);
-        match fetch_account_favourites(&account_config) {
-            Some(new_favourite) => {
-                favourites.insert(name.into(), new_favourite.into());
+        match fetch_account_toots(name, &account_config, downloads_per_host) {
+            Ok(new_checkpoints) => {
+                checkpoints.insert(name.into(), new_checkpoints);
             }
-            None => {}
+            Err(e) => println!("Error fetching {}: {:?}", name, e),
         }
     }
 
     // ... and then update it in the configuration
     let mut config = Config::open()?;
-    for (account, favourite) in favourites {
-        config.set_new_favourite(&account, &favourite);
+    for (account, new_checkpoints) in checkpoints {
+        new_checkpoints.apply(&mut config, &account);
     }
     config.save()?;
     Ok(())
 }
 
-fn fetch_account(_account: &str) -> CommandResult {
+fn fetch_account(
+    account: &str,
+    downloads_per_host: Option<usize>,
+) -> CommandResult {
+    let mut config = Config::open()?;
+    let account_config = config
+        .account(account)
+        .ok_or_else(|| CommandError::NoSuchAccount(account.into()))?;
+    log::debug!("Fetching new items from {:?}", account);
+    let checkpoints =
+        fetch_account_toots(account, account_config, downloads_per_host)?;
+    if checkpoints.apply(&mut config, account) {
+        config.save()?;
+    }
     Ok(())
 }
 
-fn fetch_account_favourites(account: &AccountConfig) -> Option<String> {
-    // XXX before anything, we could check if there is any storage enabled.
-    // XXX we could create a list of storages, so after retrieving the toot
-    //     and converting to our format, we just go through this list and call
-    //     `.save()` in each.
-    let top = account.top_favourite();
-    let mut most_recent: Option<String> = None;
+fn fetch_account_toots(
+    name: &str,
+    account: &AccountConfig,
+    downloads_per_host: Option<usize>,
+) -> Result<Checkpoints, CommandError> {
+    // The command line has priority over the account configuration.
+    let per_host = downloads_per_host
+        .or_else(|| account.downloads_per_host())
+        .unwrap_or(DEFAULT_PER_HOST);
+    let store = Rc::new(AttachmentStore::open(per_host)?);
+    let storages = Storages::open(account, &store)?;
+    // Without storages, the toots would be marked as archived without being
+    // saved anywhere.
+    if storages.is_empty() {
+        println!("No storages for {}, skipping", name);
+        return Ok(Checkpoints::default());
+    }
+
     let client = Mastodon::from(account.mastodon());
-    let markdown_storage = match account.markdown() {
-        Some(config) => Some(Markdown::new(&config)),
-        None => None,
+    let api = Api::new(&account.mastodon());
+
+    let archive = Archive {
+        name,
+        api: &api,
+        storages: &storages,
+        store: &store,
     };
-    let org_storage = match account.org() {
-        Some(config) => Some(Org::new(&config)),
-        None => None,
+    let mut checkpoints = Checkpoints {
+        favourite: archive.run(
+            Origin::Favourite,
+            api.favourites()?,
+            &account.top_favourite(),
+        )?,
+        bookmark: None,
+        status: None,
     };
-    for toot in client.favourites().ok()?.items_iter() {
-        if toot.id == top {
-            break;
+    if let Some(top) = account.top_bookmark() {
+        match api.bookmarks() {
+            // If the bookmarks stop halfway, the older ones were never seen,
+            // so the checkpoint stays where it was.
+            Ok(bookmarks) => {
+                match archive.run(Origin::Bookmark, bookmarks, &top) {
+                    Ok(newest) => checkpoints.bookmark = newest,
+                    Err(e) => {
+                        println!(
+                            "Error fetching bookmarks of {}: {:?}",
+                            name, e
+                        )
+                    }
+                }
+            }
+            Err(e) => println!("Error fetching bookmarks of {}: {:?}", name, e),
         }
+    }
+    if let Some(statuses) = account.statuses() {
+        // The statuses are listed by account ID, which we need to ask first.
+        let me = client.verify_credentials().map_err(CommandError::from);
+        match me {
+            Ok(me) => match own_statuses(&api, &me.id, statuses) {
+                // Like the bookmarks, a failure keeps the checkpoint where it
+                // was, without losing the ones of the other sources.
+                Ok(toots) => {
+                    match archive.run(Origin::Post, toots, &statuses.last()) {
+                        Ok(newest) => checkpoints.status = newest,
+                        Err(e) => println!(
+                            "Error fetching statuses of {}: {:?}",
+                            name, e
+                        ),
+                    }
+                }
+                Err(e) => {
+                    println!("Error fetching statuses of {}: {:?}", name, e)
+                }
+            },
+            Err(e) => println!("Error fetching statuses of {}: {:?}", name, e),
+        }
+    }
+    close(storages);
+    Ok(checkpoints)
+}
+
+/// The statuses of the account itself, newest first.
+fn own_statuses<'a>(
+    api: &'a Api,
+    id: &str,
+    config: &StatusesConfig,
+) -> Result<Pages<'a>, CommandError> {
+    api.statuses(id, config.replies, config.reblogs)
+}
+
+/// Everything needed to archive the toots of an account.
+struct Archive<'a> {
+    name: &'a str,
+    api: &'a Api,
+    storages: &'a Storages,
+    store: &'a AttachmentStore,
+}
+
+impl Archive<'_> {
+    /// Archive the new toots from the source, returning the new checkpoint:
+    /// the most recent toot before the first one that failed. If the source
+    /// fails before all the new toots are listed, nothing is saved and the
+    /// error is returned.
+    fn run(
+        &self,
+        source: Origin,
+        toots: impl Iterator<Item = Result<Toot, CommandError>>,
+        top: &str,
+    ) -> Result<Option<String>, CommandError> {
+        let mut seen = Seen::open(self.name, source)?;
+
+        // The server returns the most recent toots first; we collect
+        // everything up to the last seen one (or until we find a long enough
+        // run of already archived toots) and save from the oldest, so the
+        // checkpoint never moves past a toot that wasn't saved. The toots
+        // that failed in a previous run must be found, even if there are
+        // many archived toots above them.
+        let mut new_toots: Vec<Toot> = Vec::new();
+        let mut known_in_a_row = 0;
+        let mut missing = seen.retries().clone();
+        for toot in toots {
+            let toot = toot?;
+            if toot.id() == top {
+                break;
+            }
+            missing.remove(toot.id());
+
+            if seen.contains(toot.id()) {
+                known_in_a_row += 1;
+                if known_in_a_row >= KNOWN_IN_A_ROW_LIMIT && missing.is_empty()
+                {
+                    log::debug!(
+                        "Found {} archived toots in a row",
+                        known_in_a_row
+                    );
+                    break;
+                }
+            } else {
+                known_in_a_row = 0;
+            }
+            new_toots.push(toot);
+        }
+        // Whatever wasn't found isn't in the list anymore.
+        seen.forget(&missing)?;
+
+        // A toot that fails isn't marked as archived, and the checkpoint
+        // stays before it, so it is tried again in the next run; the newer
+        // ones are still saved, as the seen index keeps them from being
+        // saved twice.
+        let mut most_recent: Option<String> = None;
+        let mut failed = false;
+        for toot in new_toots.iter().rev() {
+            if seen.contains(toot.id()) {
+                log::debug!("{} {} already archived", source.name(), toot.id());
+            } else if self.save(toot, source) {
+                seen.insert(toot.id())?;
+            } else {
+                println!(
+                    "Will try {} {} again in the next run",
+                    source.name(),
+                    toot.id()
+                );
+                seen.retry_later(toot.id())?;
+                failed = true;
+            }
+
+            if !failed {
+                most_recent = Some(toot.id().to_string());
+            }
+        }
+        Ok(most_recent)
+    }
+
+    /// Convert the toot, with its conversation, and save it in every
+    /// storage, returning whether it was saved everywhere.
+    fn save(&self, toot: &Toot, source: Origin) -> bool {
+        // Boosts are saved as the boosted toot, but tracked by the ID of
+        // the boost itself.
+        let (original, origin) = match toot.reblog() {
+            Some(reblog) => (reblog, Origin::Boost),
+            None => (toot.clone(), source),
+        };
+        let mut conversion = Data::from(&original);
+        conversion.origin = origin;
+        println!("Found new {}: {}", origin.name(), toot.id());
+        // Nothing is downloaded for a toot no storage keeps.
+        if !self.storages.accepts(&conversion) {
+            println!("Skipping {} {}, filtered out", origin.name(), toot.id());
+            return true;
+        }
+        // The conversation is only fetched if some storage will save it.
+        if self.storages.wants_thread(&conversion) {
+            match self.api.context(original.id()) {
+                Ok((ancestors, descendants)) => {
+                    conversion.set_context(&ancestors, &descendants)
+                }
+                Err(e) => {
+                    println!(
+                        "Failed to fetch the conversation of {} {}: {:?}",
+                        origin.name(),
+                        toot.id(),
+                        e
+                    );
+                    return false;
+                }
+            }
+        }
+        store_all(self.storages, self.store, &conversion, toot.id())
+    }
+}
+
+/// Save the converted toot in every storage that accepts it, returning
+/// whether it was saved everywhere; the toot must be accepted by at least one
+/// storage, or its attachments would be downloaded for nothing.
+fn store_all(
+    storages: &Storages,
+    store: &AttachmentStore,
+    conversion: &Data,
+    id: &str,
+) -> bool {
+    store.prefetch(&conversion.attachments);
+
+    let errors = storages.save(conversion);
+    for error in errors.iter() {
+        println!(
+            "Failed to save {} {}: {:?}",
+            conversion.origin.name(),
+            id,
+            error
+        );
+    }
+    errors.is_empty()
+}
+
+/// Close the storages at the end of a run, reporting the ones that failed.
+fn close(storages: Storages) {
+    for error in storages.close() {
+        println!("Failed to close storage: {:?}", error);
+    }
+}
+
+fn import_archive(
+    account: &str,
+    path: &str,
+    resolve: bool,
+    downloads_per_host: Option<usize>,
+) -> CommandResult {
+    let config = Config::open()?;
+    let account_config = config
+        .account(account)
+        .ok_or_else(|| CommandError::NoSuchAccount(account.into()))?;
+    let mut export = Export::open(Path::new(path), &account_config.server())?;
+
+    // The conversations are not fetched, and the server is only used to find
+    // the toots the archive has only the address of, if asked to.
+    let api = if resolve {
+        Some(Api::new(&account_config.mastodon()))
+    } else {
+        None
+    };
+    // The command line has priority over the account configuration.
+    let store = Rc::new(AttachmentStore::open(
+        downloads_per_host
+            .or_else(|| account_config.downloads_per_host())
+            .unwrap_or(DEFAULT_PER_HOST),
+    )?);
+    let storages = Storages::open(account_config, &store)?;
+
+    let sources = vec![
+        (Origin::Post, export.outbox()?),
+        (Origin::Favourite, export.likes()?),
+        (Origin::Bookmark, export.bookmarks()?),
+    ];
+    let addresses = sources
+        .iter()
+        .flat_map(|(_, toots)| toots.iter())
+        .filter(|toot| toot.status.is_none())
+        .count();
+    if addresses > 0 && api.is_none() {
+        println!(
+            "The archive only has the address of {} toots (favourites, \
+             bookmarks and boosts of other accounts), which won't be \
+             imported; use --resolve to fetch them from the server",
+            addresses
+        );
+    }
 
-        if most_recent.is_none() {
-            most_recent = Some((&toot.id).into());
+    let mut failed = 0;
+    let mut missing = 0;
+    for (source, toots) in sources {
+        let mut seen = Seen::open(account, source)?;
+        for toot in toots {
+            if seen.contains(&toot.id) {
+                log::debug!("{} {} already archived", source.name(), toot.id);
+                continue;
+            }
+
+            let resolved = toot.status.is_none();
+            let status = match (toot.status, &api) {
+                (Some(status), _) => status,
+                (None, Some(api)) => match api.resolve(&toot.uri) {
+                    Ok(Some(status)) => status,
+                    Ok(None) => {
+                        println!("Toot {} not found", toot.uri);
+                        missing += 1;
+                        continue;
+                    }
+                    Err(e) => {
+                        println!("Failed to fetch {}: {:?}", toot.uri, e);
+                        failed += 1;
+                        continue;
+                    }
+                },
+                (None, None) => continue,
+            };
+            // Once resolved, favourites and bookmarks are tracked by the ID
+            // in our server, like the fetched ones; boosts are tracked by the
+            // ID of the boost, which the archive already has.
+            let id = if resolved && toot.origin != Origin::Boost {
+                status.id().to_string()
+            } else {
+                toot.id
+            };
+            if seen.contains(&id) {
+                log::debug!("{} {} already archived", source.name(), id);
+                continue;
+            }
+
+            let mut conversion = Data::from(&status);
+            conversion.origin = toot.origin;
+            println!("Found new {}: {}", toot.origin.name(), id);
+            if !storages.accepts(&conversion) {
+                println!(
+                    "Skipping {} {}, filtered out",
+                    toot.origin.name(),
+                    id
+                );
+                seen.insert(&id)?;
+                continue;
+            }
+            for attachment in conversion.attachments.iter() {
+                if let Some(mut content) = export.media(attachment.url())? {
+                    store.import(attachment, &mut content)?;
+                }
+            }
+            // A toot that fails is not marked as archived, so importing the
+            // archive again tries it again.
+            if store_all(&storages, &store, &conversion, &id) {
+                seen.insert(&id)?;
+            } else {
+                failed += 1;
+            }
         }
+    }
 
-        let conversion = Data::from(&toot);
-        println!("Found new favourite: {}", conversion.id);
+    close(storages);
 
-        if let Some(storage) = markdown_storage.as_ref() {
-            storage.save(&conversion);
+    if missing > 0 {
+        println!("{} toots couldn't be found in the server", missing);
+    }
+    if failed > 0 {
+        println!("{} toots couldn't be saved", failed);
+    }
+    Ok(())
+}
+
+fn sync_account(account: &str) -> CommandResult {
+    let mut config = Config::open()?;
+    let account_config = config
+        .account(account)
+        .ok_or_else(|| CommandError::NoSuchAccount(account.into()))?;
+    let previous = account_config.top_favourite();
+    let previous_bookmark = account_config.top_bookmark();
+    let client = Mastodon::from(account_config.mastodon());
+    let api = Api::new(&account_config.mastodon());
+    let newest = api
+        .favourites()?
+        .next()
+        .transpose()?
+        .map(|toot| toot.id().to_string());
+    let previous_status =
+        account_config.statuses().map(|statuses| statuses.last());
+    let newest_status = match account_config.statuses() {
+        Some(statuses) => {
+            let me = client.verify_credentials()?;
+            own_statuses(&api, &me.id, statuses)?
+                .next()
+                .transpose()?
+                .map(|toot| toot.id().to_string())
         }
+        None => None,
+    };
+    let newest_bookmark = match previous_bookmark {
+        Some(_) => api
+            .bookmarks()?
+            .next()
+            .transpose()?
+            .map(|toot| toot.id().to_string()),
+        None => None,
+    };
+
+    match newest {
+        Some(favourite) => {
+            config.set_new_favourite(account, &favourite);
+            println!("Last seen favourite: {} -> {}", previous, favourite);
+        }
+        None => {
+            println!("No favourites found, last seen favourite is {}", previous)
+        }
+    }
+    if let (Some(previous), Some(bookmark)) =
+        (previous_bookmark, newest_bookmark)
+    {
+        config.set_new_bookmark(account, &bookmark);
+        println!("Last seen bookmark: {} -> {}", previous, bookmark);
+    }
+    if let (Some(previous), Some(status)) = (previous_status, newest_status) {
+        config.set_new_status(account, &status);
+        println!("Last seen status: {} -> {}", previous, status);
+    }
+    config.save()?;
+    Ok(())
+}
 
-        if let Some(storage) = org_storage.as_ref() {
-            storage.save(&conversion);
+fn list_accounts(format: &OutputFormat) -> CommandResult {
+    let config = Config::open()?;
+    let mut accounts: Vec<AccountSummary> = config
+        .into_iter()
+        .map(|(name, account_config)| AccountSummary::new(name, account_config))
+        .collect();
+    accounts.sort_by(|a, b| a.name().cmp(b.name()));
+
+    match format {
+        OutputFormat::Table => summary::print_table(&accounts),
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&accounts)?)
         }
     }
-    most_recent
+    Ok(())
 }
 
-fn sync_account(_account: &str) -> CommandResult {
+fn show_account(account: &str, format: &OutputFormat) -> CommandResult {
+    let config = Config::open()?;
+    let account_config = config
+        .account(account)
+        .ok_or_else(|| CommandError::NoSuchAccount(account.into()))?;
+    let summary = AccountSummary::new(account, account_config);
+
+    match format {
+        OutputFormat::Table => summary.print(),
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&summary)?)
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,155 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::rc::Rc;
+
+use crate::config::account::AccountConfig;
+use crate::config::filter::Filter;
+use crate::config::storage::StorageConfig;
+use crate::storage::data::Data;
+use crate::storage::errors::StorageError;
+use crate::storage::feed::storage::Feed;
+use crate::storage::html::storage::Html;
+use crate::storage::json::storage::Json;
+use crate::storage::markdown::storage::Markdown;
+use crate::storage::org::storage::Org;
+use crate::storage::sqlite::storage::Sqlite;
+use crate::storage::storage::Storage;
+use crate::storage::store::AttachmentStore;
+use crate::storage::thread::ThreadMode;
+
+/// The storages enabled for an account, during a single run.
+///
+/// Every storage is initialized when the list is opened, and closed when the
+/// run ends -- either by `close`, or when the list is dropped, so even a run
+/// that fails halfway gives the storages the chance to write what they kept
+/// for the end.
+pub struct Storages {
+    /// The storages, with the filter of the toots each one keeps.
+    storages: Vec<(Filter, Box<dyn Storage>)>,
+    closed: bool,
+}
+
+impl Storages {
+    /// Build and initialize the storages of the account.
+    pub fn open(
+        account: &AccountConfig,
+        store: &Rc<AttachmentStore>,
+    ) -> Result<Self, StorageError> {
+        let mut storages = Self {
+            storages: Vec::new(),
+            closed: false,
+        };
+        for entry in account.storages() {
+            let filter = entry.filter.clone();
+            match &entry.config {
+                StorageConfig::Markdown(config) => storages
+                    .add(filter, Markdown::new(config, Rc::clone(store)))?,
+                StorageConfig::Org(config) => {
+                    storages.add(filter, Org::new(config, Rc::clone(store)))?
+                }
+                StorageConfig::Json(config) => {
+                    storages.add(filter, Json::new(config, Rc::clone(store)))?
+                }
+                StorageConfig::Sqlite(config) => storages
+                    .add(filter, Sqlite::new(config, Rc::clone(store))?)?,
+                StorageConfig::Html(config) => {
+                    storages.add(filter, Html::new(config, Rc::clone(store)))?
+                }
+                StorageConfig::Feed(config) => {
+                    storages.add(filter, Feed::new(config))?
+                }
+            }
+        }
+        Ok(storages)
+    }
+
+    /// Initialize the storage and add it to the list; if the initialization
+    /// fails, the storages already in the list are closed when it is dropped.
+    fn add(
+        &mut self,
+        filter: Filter,
+        storage: impl Storage + 'static,
+    ) -> Result<(), StorageError> {
+        storage.init()?;
+        self.storages.push((filter, Box::new(storage)));
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.storages.is_empty()
+    }
+
+    /// Whether any storage keeps the toot.
+    pub fn accepts(&self, data: &Data) -> bool {
+        self.accepting(data).next().is_some()
+    }
+
+    /// Whether any storage that keeps the toot wants the conversation around
+    /// it.
+    pub fn wants_thread(&self, data: &Data) -> bool {
+        self.accepting(data)
+            .any(|storage| storage.thread() != ThreadMode::Off)
+    }
+
+    /// Save the toot in every storage its filter accepts, returning the
+    /// errors of the ones that failed.
+    pub fn save(&self, data: &Data) -> Vec<StorageError> {
+        self.accepting(data)
+            .filter_map(|storage| storage.save(data).err())
+            .collect()
+    }
+
+    /// The storages with a filter that accepts the toot.
+    fn accepting<'a>(
+        &'a self,
+        data: &'a Data,
+    ) -> impl Iterator<Item = &'a dyn Storage> + 'a {
+        self.storages.iter().filter_map(move |(filter, storage)| {
+            if filter.accepts(data) {
+                Some(storage.as_ref())
+            } else {
+                log::debug!("Toot {} filtered out of a storage", data.id);
+                None
+            }
+        })
+    }
+
+    /// Close every storage, returning the errors of the ones that failed.
+    pub fn close(mut self) -> Vec<StorageError> {
+        self.close_all()
+    }
+
+    fn close_all(&mut self) -> Vec<StorageError> {
+        self.closed = true;
+        self.storages
+            .iter()
+            .filter_map(|(_, storage)| storage.close().err())
+            .collect()
+    }
+}
+
+impl Drop for Storages {
+    fn drop(&mut self) {
+        if !self.closed {
+            for error in self.close_all() {
+                println!("Failed to close storage: {:?}", error);
+            }
+        }
+    }
+}
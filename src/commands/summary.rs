@@ -0,0 +1,151 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use serde_derive::Serialize;
+
+use crate::config::account::AccountConfig;
+
+/// A storage, as displayed to the user.
+#[derive(Serialize, Debug)]
+pub struct StorageSummary {
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    path: String,
+}
+
+/// Account information that is safe to display to the user; the access
+/// token, client ID and client secret are never part of it.
+#[derive(Serialize, Debug)]
+pub struct AccountSummary {
+    name: String,
+    server: String,
+    storages: Vec<StorageSummary>,
+    last_favourite: String,
+    /// Only present if the bookmarks are archived.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_bookmark: Option<String>,
+    /// Only present if the account's own statuses are archived.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_status: Option<String>,
+}
+
+impl AccountSummary {
+    pub fn new(name: &str, account: &AccountConfig) -> Self {
+        let storages = account
+            .storages()
+            .iter()
+            .map(|entry| StorageSummary {
+                name: entry.name.to_string(),
+                kind: entry.config.kind().into(),
+                path: entry.config.path().into(),
+            })
+            .collect();
+
+        Self {
+            name: name.into(),
+            server: account.server(),
+            storages,
+            last_favourite: account.top_favourite(),
+            last_bookmark: account.top_bookmark(),
+            last_status: account.statuses().map(|statuses| statuses.last()),
+        }
+    }
+
+    /// The account alias.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The storage names, in a single line.
+    fn storage_names(&self) -> String {
+        if self.storages.is_empty() {
+            "-".into()
+        } else {
+            self.storages
+                .iter()
+                .map(|storage| storage.name.as_str())
+                .collect::<Vec<&str>>()
+                .join(",")
+        }
+    }
+
+    /// Print the account details, one per line.
+    pub fn print(&self) {
+        println!("Account:        {}", self.name);
+        println!("Server:         {}", self.server);
+        println!("Last favourite: {}", self.last_favourite);
+        if let Some(last_bookmark) = &self.last_bookmark {
+            println!("Last bookmark:  {}", last_bookmark);
+        }
+        if let Some(last_status) = &self.last_status {
+            println!("Last status:    {}", last_status);
+        }
+        if self.storages.is_empty() {
+            println!("Storages:       -");
+        } else {
+            println!("Storages:");
+            for storage in self.storages.iter() {
+                println!(
+                    "  {:<10} {:<10} {}",
+                    storage.name, storage.kind, storage.path
+                );
+            }
+        }
+    }
+}
+
+/// Print a list of accounts as a table.
+pub fn print_table(accounts: &[AccountSummary]) {
+    let headers = ["ALIAS", "SERVER", "STORAGES", "LAST FAVOURITE"];
+    let rows: Vec<[String; 4]> = accounts
+        .iter()
+        .map(|account| {
+            [
+                account.name.to_string(),
+                account.server.to_string(),
+                account.storage_names(),
+                account.last_favourite.to_string(),
+            ]
+        })
+        .collect();
+
+    let mut widths: Vec<usize> =
+        headers.iter().map(|name| name.len()).collect();
+    for row in rows.iter() {
+        for (width, column) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(column.len());
+        }
+    }
+
+    let format_row = |columns: Vec<&str>| {
+        columns
+            .iter()
+            .zip(widths.iter())
+            .map(|(column, width)| format!("{:<width$}", column, width = width))
+            .collect::<Vec<String>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    println!("{}", format_row(headers.to_vec()));
+    for row in rows.iter() {
+        println!("{}", format_row(row.iter().map(String::as_str).collect()));
+    }
+}
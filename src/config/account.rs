@@ -23,16 +23,48 @@ use serde_derive::Deserialize;
 use serde_derive::Serialize;
 
 use super::favourite::Favourite;
+use super::filter::Filter;
+use super::statuses::StatusesConfig;
+use super::storage::StorageConfig;
+use super::storage::StorageEntry;
+use crate::storage::feed::config::FeedConfig;
+use crate::storage::html::config::HtmlConfig;
+use crate::storage::json::config::JsonConfig;
 use crate::storage::markdown::config::MarkdownConfig;
 use crate::storage::org::config::OrgConfig;
+use crate::storage::sqlite::config::SqliteConfig;
 
 /// Account configuration
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AccountConfig {
+    // TOML can't have plain values after tables, so those must come first.
+    /// How many attachments can be downloaded at the same time from the same
+    /// server.
+    downloads_per_host: Option<usize>,
     favourite: Favourite,
+    /// The last seen bookmark; bookmarks are only archived if this is set.
+    bookmark: Option<Favourite>,
+    /// The account's own statuses are only archived if this is set.
+    statuses: Option<StatusesConfig>,
     mastodon: Data,
+    // Storages from older versions, which are moved to `storages` when the
+    // configuration is open.
+    #[serde(default, skip_serializing)]
     markdown: Option<MarkdownConfig>,
+    #[serde(default, skip_serializing)]
     org: Option<OrgConfig>,
+    #[serde(default, skip_serializing)]
+    json: Option<JsonConfig>,
+    #[serde(default, skip_serializing)]
+    sqlite: Option<SqliteConfig>,
+    #[serde(default, skip_serializing)]
+    html: Option<HtmlConfig>,
+    #[serde(default, skip_serializing)]
+    feed: Option<FeedConfig>,
+    /// Where the toots are saved; an empty list would be written as a value,
+    /// after the tables, so it is left out.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    storages: Vec<StorageEntry>,
 }
 
 impl AccountConfig {
@@ -42,8 +74,16 @@ impl AccountConfig {
         Self {
             mastodon: configuration,
             favourite: Favourite::default(),
+            bookmark: None,
+            statuses: None,
             markdown: None,
             org: None,
+            json: None,
+            sqlite: None,
+            html: None,
+            feed: None,
+            downloads_per_host: None,
+            storages: Vec::new(),
         }
     }
 
@@ -58,42 +98,164 @@ impl AccountConfig {
         self.favourite.set(favourite);
     }
 
+    /// Return the top bookmark for the account, if bookmarks are archived.
+    #[logfn(Trace)]
+    pub fn top_bookmark(&self) -> Option<String> {
+        self.bookmark.as_ref().map(|bookmark| bookmark.last())
+    }
+
+    #[logfn_inputs(Trace)]
+    pub fn set_bookmark(&mut self, bookmark: &str) {
+        if let Some(last) = self.bookmark.as_mut() {
+            last.set(bookmark);
+        }
+    }
+
+    /// Start archiving bookmarks.
+    pub fn enable_bookmarks(&mut self) {
+        if self.bookmark.is_none() {
+            self.bookmark = Some(Favourite::default());
+        }
+    }
+
+    /// Stop archiving bookmarks.
+    pub fn disable_bookmarks(&mut self) {
+        self.bookmark = None;
+    }
+
+    /// Return the configuration for archiving the account's own statuses, if
+    /// they are archived.
+    pub fn statuses(&self) -> Option<&StatusesConfig> {
+        self.statuses.as_ref()
+    }
+
+    /// Set the last seen status of the account itself.
+    #[logfn_inputs(Trace)]
+    pub fn set_status(&mut self, status: &str) {
+        if let Some(statuses) = self.statuses.as_mut() {
+            statuses.set(status);
+        }
+    }
+
+    /// Start archiving the account's own statuses.
+    #[logfn_inputs(Trace)]
+    pub fn set_statuses(&mut self, config: StatusesConfig) {
+        self.statuses = Some(config);
+    }
+
+    /// Stop archiving the account's own statuses.
+    pub fn remove_statuses(&mut self) {
+        self.statuses = None;
+    }
+
     /// The Mastodon configuration for the account.
     pub fn mastodon(&self) -> Data {
         self.mastodon.clone()
     }
 
-    /// Set the Markdown configuration.
-    #[logfn_inputs(Trace)]
-    pub fn set_markdown(&mut self, config: MarkdownConfig) {
-        self.markdown = Some(config);
+    /// The server the account belongs to.
+    pub fn server(&self) -> String {
+        self.mastodon.base.to_string()
     }
 
-    /// Remove the Markdown configuration.
-    #[logfn_inputs(Trace)]
-    pub fn remove_markdown(&mut self) {
-        self.markdown = None;
+    /// How many attachments can be downloaded at the same time from the same
+    /// server, if defined.
+    pub fn downloads_per_host(&self) -> Option<usize> {
+        self.downloads_per_host
     }
 
-    /// Return the Markdown configuration.
-    #[logfn(Trace)]
-    pub fn markdown(&self) -> &Option<MarkdownConfig> {
-        &self.markdown
+    /// The storages of the account, in the order toots are saved in them.
+    pub fn storages(&self) -> &[StorageEntry] {
+        &self.storages
     }
 
-    /// Set the Org configuration.
+    /// Add a storage to the account, returning false if there is already
+    /// a storage with the same name.
     #[logfn_inputs(Trace)]
-    pub fn set_org(&mut self, config: OrgConfig) {
-        self.org = Some(config);
+    pub fn add_storage(&mut self, entry: StorageEntry) -> bool {
+        if self
+            .storages
+            .iter()
+            .any(|storage| storage.name == entry.name)
+        {
+            return false;
+        }
+        self.storages.push(entry);
+        true
     }
 
-    /// Remove the Org configuration.
-    pub fn remove_org(&mut self) {
-        self.org = None;
+    /// Remove the storage with the name, returning false if there is none.
+    #[logfn_inputs(Trace)]
+    pub fn remove_storage(&mut self, name: &str) -> bool {
+        let before = self.storages.len();
+        self.storages.retain(|storage| storage.name != name);
+        self.storages.len() != before
+    }
+
+    /// Older versions had a single storage of each type; move them to the
+    /// list of storages, named after their type, returning whether there was
+    /// anything to move.
+    pub fn migrate_storages(&mut self) -> bool {
+        let old = vec![
+            self.markdown.take().map(StorageConfig::Markdown),
+            self.org.take().map(StorageConfig::Org),
+            self.json.take().map(StorageConfig::Json),
+            self.sqlite.take().map(StorageConfig::Sqlite),
+            self.html.take().map(StorageConfig::Html),
+            self.feed.take().map(StorageConfig::Feed),
+        ];
+        let mut migrated = false;
+        for config in old.into_iter().flatten() {
+            let mut name = config.kind().to_string();
+            let mut suffix = 1;
+            while self.storages.iter().any(|storage| storage.name == name) {
+                suffix += 1;
+                name = format!("{}-{}", config.kind(), suffix);
+            }
+            self.storages.push(StorageEntry {
+                name,
+                config,
+                filter: Filter::default(),
+            });
+            migrated = true;
+        }
+        migrated
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn round_trip_with_downloads_per_host() {
+        let mut account = AccountConfig::new(Data {
+            base: "https://example.com".into(),
+            client_id: "id".into(),
+            client_secret: "secret".into(),
+            redirect: "urn:ietf:wg:oauth:2.0:oob".into(),
+            token: "token".into(),
+        });
+        account.enable_bookmarks();
+        account.downloads_per_host = Some(2);
+        account.add_storage(StorageEntry {
+            name: "markdown".into(),
+            config: StorageConfig::Markdown(MarkdownConfig {
+                path: "/tmp/toots".into(),
+                thread: Default::default(),
+            }),
+            filter: Filter::default(),
+        });
+        let mut config = HashMap::new();
+        config.insert("test".to_string(), account);
 
-    /// Return the Org configuration.
-    pub fn org(&self) -> &Option<OrgConfig> {
-        &self.org
+        // The same conversion `Config::save` does.
+        let content = toml::to_string(&config).unwrap();
+        let loaded: HashMap<String, AccountConfig> =
+            toml::from_str(&content).unwrap();
+        assert_eq!(loaded["test"].downloads_per_host(), Some(2));
+        assert_eq!(loaded["test"].storages().len(), 1);
     }
 }
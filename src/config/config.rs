@@ -20,7 +20,9 @@ use std::collections::hash_map::Iter;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::prelude::*;
+use std::path::Path;
 use std::path::PathBuf;
+use std::sync::OnceLock;
 
 use directories::ProjectDirs;
 use elefren::Data;
@@ -31,33 +33,110 @@ use serde_derive::Serialize;
 
 use super::account::AccountConfig;
 use crate::config::errors::ConfigError;
-use crate::storage::markdown::config::MarkdownConfig;
-use crate::storage::org::config::OrgConfig;
+use crate::config::statuses::StatusesConfig;
+use crate::config::storage::StorageEntry;
+
+/// Configuration file selected in the command line, if any.
+static CUSTOM_FILENAME: OnceLock<PathBuf> = OnceLock::new();
 
 /// The main configuration
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config(HashMap<String, AccountConfig>);
 
 impl Config {
-    /// Figure out the filename for the configuration file.
+    /// Use a different configuration file instead of the default one.
+    ///
+    /// This must be called before the configuration is open for the first
+    /// time; any other calls are ignored.
+    #[logfn_inputs(Trace)]
+    pub fn use_file(filename: &Path) {
+        if CUSTOM_FILENAME.set(filename.into()).is_err() {
+            log::debug!("Configuration file already defined, ignoring");
+        }
+    }
+
+    /// The directory where downfav keeps its files.
     #[logfn(Trace)]
-    fn filename() -> Result<PathBuf, ConfigError> {
-        match ProjectDirs::from("me", "JulioBiason", "downfav.toml") {
+    pub(crate) fn directory() -> Result<PathBuf, ConfigError> {
+        if let Some(filename) = CUSTOM_FILENAME.get() {
+            // A file without a directory (`--config foo.toml`) is in the
+            // current one.
+            return Ok(match filename.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.into(),
+                _ => PathBuf::from("."),
+            });
+        }
+
+        match ProjectDirs::from("me", "JulioBiason", "downfav") {
             Some(proj_dirs) => Ok(proj_dirs.config_dir().into()),
             None => Err(ConfigError::CantFigureConfigPath),
         }
     }
 
+    /// Figure out the filename for the configuration file.
+    #[logfn(Trace)]
+    fn filename() -> Result<PathBuf, ConfigError> {
+        match CUSTOM_FILENAME.get() {
+            Some(filename) => Ok(filename.clone()),
+            None => Ok(Config::directory()?.join("config.toml")),
+        }
+    }
+
+    /// Older versions used the configuration directory path as the
+    /// configuration file; if that file is still around (and there is no
+    /// configuration in the proper place), move it to the new location.
+    #[logfn_inputs(Trace)]
+    fn migrate(filename: &Path) -> Result<(), ConfigError> {
+        if CUSTOM_FILENAME.get().is_some() || filename.exists() {
+            return Ok(());
+        }
+
+        let old_filename =
+            match ProjectDirs::from("me", "JulioBiason", "downfav.toml") {
+                Some(proj_dirs) => proj_dirs.config_dir().to_path_buf(),
+                None => return Ok(()),
+            };
+        if old_filename.is_file() {
+            if let Some(parent) = filename.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::rename(&old_filename, filename)?;
+            println!(
+                "Configuration moved from {} to {}",
+                old_filename.to_string_lossy(),
+                filename.to_string_lossy()
+            );
+        }
+        Ok(())
+    }
+
+    /// Move the storages of older versions, one of each type, to the list of
+    /// named storages of each account.
+    fn migrate_storages(&mut self) -> bool {
+        let mut migrated = false;
+        for (name, account_config) in self.0.iter_mut() {
+            if account_config.migrate_storages() {
+                println!("Storages of {} moved to the list of storages", name);
+                migrated = true;
+            }
+        }
+        migrated
+    }
+
     /// Open the configuration file; if it doesn't exist, returns an empty set.
     pub fn open() -> Result<Self, ConfigError> {
         let filename = Config::filename()?;
+        Config::migrate(&filename)?;
         log::debug!("Trying to open file {:?}", filename);
         match File::open(filename) {
             Ok(mut fp) => {
                 let mut contents = String::new();
                 fp.read_to_string(&mut contents)?;
-                let parsed = toml::from_str(&contents)?;
-                Ok(Self(parsed))
+                let mut config = Self(toml::from_str(&contents)?);
+                if config.migrate_storages() {
+                    config.save()?;
+                }
+                Ok(config)
             }
             Err(_) => Ok(Self(HashMap::new())),
         }
@@ -76,51 +155,91 @@ impl Config {
         self.0.remove(name);
     }
 
-    /// Set the configuration for the markdown storage
+    /// Return the configuration for the account, if it exists.
+    #[logfn_inputs(Trace)]
+    pub fn account(&self, name: &str) -> Option<&AccountConfig> {
+        self.0.get(name)
+    }
+
+    /// Add a storage to the account, returning false if the account
+    /// doesn't exist or already has a storage with the same name.
     #[logfn_inputs(Trace)]
-    pub fn set_storage_markdown(
-        &mut self,
-        account: &str,
-        config: MarkdownConfig,
-    ) {
+    pub fn add_storage(&mut self, account: &str, entry: StorageEntry) -> bool {
         match self.0.get_mut(account.into()) {
-            Some(account_config) => account_config.set_markdown(config),
+            Some(account_config) => account_config.add_storage(entry),
+            None => false,
+        }
+    }
+
+    /// Remove a storage from the account, returning false if there is no
+    /// storage with the name.
+    #[logfn_inputs(Trace)]
+    pub fn remove_storage(&mut self, account: &str, name: &str) -> bool {
+        match self.0.get_mut(account.into()) {
+            Some(account_config) => account_config.remove_storage(name),
+            None => false,
+        }
+    }
+
+    /// Set the a last seen favourite for the account
+    #[logfn_inputs(Trace)]
+    pub fn set_new_favourite(&mut self, account: &str, favourite: &str) {
+        match self.0.get_mut(account.into()) {
+            Some(account_config) => account_config.set_favourite(favourite),
             None => {}
         }
     }
 
-    /// Remove the Markdown storage from the account.
+    /// Set the last seen bookmark for the account.
     #[logfn_inputs(Trace)]
-    pub fn remove_storage_markdown(&mut self, account: &str) {
+    pub fn set_new_bookmark(&mut self, account: &str, bookmark: &str) {
         match self.0.get_mut(account.into()) {
-            Some(account_config) => account_config.remove_markdown(),
+            Some(account_config) => account_config.set_bookmark(bookmark),
             None => {}
         }
     }
 
-    /// Set the configuration for the Org storage.
+    /// Set the last seen status of the account itself.
     #[logfn_inputs(Trace)]
-    pub fn set_storage_org(&mut self, account: &str, config: OrgConfig) {
+    pub fn set_new_status(&mut self, account: &str, status: &str) {
         match self.0.get_mut(account.into()) {
-            Some(account_config) => account_config.set_org(config),
+            Some(account_config) => account_config.set_status(status),
             None => {}
         }
     }
 
-    /// Remove the Org storage.
+    /// Start archiving the statuses of the account itself.
     #[logfn_inputs(Trace)]
-    pub fn remove_storage_org(&mut self, account: &str) {
+    pub fn set_statuses(&mut self, account: &str, statuses: StatusesConfig) {
         match self.0.get_mut(account.into()) {
-            Some(account_config) => account_config.remove_org(),
+            Some(account_config) => account_config.set_statuses(statuses),
             None => {}
         }
     }
 
-    /// Set the a last seen favourite for the account
+    /// Stop archiving the statuses of the account itself.
     #[logfn_inputs(Trace)]
-    pub fn set_new_favourite(&mut self, account: &str, favourite: &str) {
+    pub fn remove_statuses(&mut self, account: &str) {
         match self.0.get_mut(account.into()) {
-            Some(account_config) => account_config.set_favourite(favourite),
+            Some(account_config) => account_config.remove_statuses(),
+            None => {}
+        }
+    }
+
+    /// Start archiving the bookmarks of the account.
+    #[logfn_inputs(Trace)]
+    pub fn enable_bookmarks(&mut self, account: &str) {
+        match self.0.get_mut(account.into()) {
+            Some(account_config) => account_config.enable_bookmarks(),
+            None => {}
+        }
+    }
+
+    /// Stop archiving the bookmarks of the account.
+    #[logfn_inputs(Trace)]
+    pub fn disable_bookmarks(&mut self, account: &str) {
+        match self.0.get_mut(account.into()) {
+            Some(account_config) => account_config.disable_bookmarks(),
             None => {}
         }
     }
@@ -130,6 +249,9 @@ impl Config {
         let content = toml::to_string(&self.0)?;
         let filename = Config::filename()?;
         log::debug!("Saving configuration to file {:?}", filename);
+        if let Some(parent) = filename.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
         let mut fp = File::create(filename)?;
         fp.write_all(content.as_bytes())?;
         Ok(())
@@ -0,0 +1,354 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use regex::Regex;
+use serde::de::Error;
+use serde::Deserializer;
+use serde::Serializer;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+
+use crate::storage::data::Data;
+use crate::storage::document::html;
+use crate::storage::document::Document;
+
+/// Which toots a storage keeps: only those matching the `only` rule (if
+/// there is one), and none of those matching any of the `skip` rules.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Filter {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    only: Option<Rule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    skip: Vec<Rule>,
+}
+
+impl Filter {
+    /// Whether the toot should be saved.
+    pub fn accepts(&self, data: &Data) -> bool {
+        if let Some(only) = &self.only {
+            if !only.matches(data) {
+                return false;
+            }
+        }
+        !self.skip.iter().any(|rule| rule.matches(data))
+    }
+}
+
+/// A set of conditions on a toot; the rule matches when all the conditions
+/// it has match. Lists match when any of their values does.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct Rule {
+    /// Accounts of the authors, as `user@server`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    authors: Vec<String>,
+    /// Hashtags, with or without the `#`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// Whether the toot has attachments.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    media: Option<bool>,
+    /// Language codes, like `en`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    languages: Vec<String>,
+    /// Text in the content warning; an empty text matches any content
+    /// warning.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spoiler: Option<String>,
+    /// Regular expression on the text of the toot, without formatting.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<Pattern>,
+}
+
+impl Rule {
+    fn matches(&self, data: &Data) -> bool {
+        let author = data.account.to_lowercase();
+        if !self.authors.is_empty()
+            && !self.authors.iter().any(|wanted| {
+                wanted.trim_start_matches('@').to_lowercase() == author
+            })
+        {
+            return false;
+        }
+
+        if !self.tags.is_empty()
+            && !self.tags.iter().any(|wanted| {
+                let wanted = wanted.trim_start_matches('#');
+                data.tags.iter().any(|tag| tag.eq_ignore_ascii_case(wanted))
+            })
+        {
+            return false;
+        }
+
+        if let Some(media) = self.media {
+            if media == data.attachments.is_empty() {
+                return false;
+            }
+        }
+
+        if !self.languages.is_empty() {
+            match &data.language {
+                Some(language)
+                    if self.languages.iter().any(|wanted| {
+                        wanted.eq_ignore_ascii_case(language)
+                    }) => {}
+                _ => return false,
+            }
+        }
+
+        if let Some(spoiler) = &self.spoiler {
+            if data.title.is_empty()
+                || !data.title.to_lowercase().contains(&spoiler.to_lowercase())
+            {
+                return false;
+            }
+        }
+
+        if let Some(pattern) = &self.content {
+            let text = html::text(&Document::parse(&data.text, &[]));
+            if !pattern.0.is_match(&text) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// A regular expression, kept in the configuration as its source.
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl serde::Serialize for Pattern {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let source: String = serde::Deserialize::deserialize(deserializer)?;
+        Regex::new(&source).map(Pattern).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::storage::data::Toot;
+
+    /// A toot by the author, with the content, content warning, language,
+    /// hashtags and number of attachments.
+    fn toot(
+        acct: &str,
+        content: &str,
+        spoiler: &str,
+        language: Option<&str>,
+        tags: &[&str],
+        media: usize,
+    ) -> Data {
+        let attachments: Vec<Value> = (0..media)
+            .map(|position| {
+                json!({
+                    "id": position.to_string(),
+                    "type": "image",
+                    "url": format!("https://m.s/{}.png", position),
+                    "preview_url": format!("https://m.s/{}-small.png", position),
+                })
+            })
+            .collect();
+        let json = json!({
+            "id": "1",
+            "uri": "https://m.s/users/bob/statuses/1",
+            "url": "https://m.s/@bob/1",
+            "account": {
+                "id": "1",
+                "acct": acct,
+                "username": acct.split('@').next(),
+                "display_name": "Bob",
+                "avatar": "",
+                "avatar_static": "",
+                "header": "",
+                "header_static": "",
+                "created_at": "2020-01-01T00:00:00Z",
+                "followers_count": 0,
+                "following_count": 0,
+                "statuses_count": 0,
+                "locked": false,
+                "note": "",
+                "url": "https://m.s/@bob",
+            },
+            "in_reply_to_id": null,
+            "in_reply_to_account_id": null,
+            "reblog": null,
+            "content": content,
+            "created_at": "2020-01-01T00:00:00Z",
+            "emojis": [],
+            "reblogs_count": 0,
+            "favourites_count": 0,
+            "sensitive": false,
+            "spoiler_text": spoiler,
+            "visibility": "public",
+            "media_attachments": attachments,
+            "mentions": [],
+            "tags": tags
+                .iter()
+                .map(|tag| json!({ "name": tag, "url": "" }))
+                .collect::<Vec<Value>>(),
+            "language": language,
+        });
+        Data::from(&Toot::parse(json).unwrap())
+    }
+
+    fn filter(config: &str) -> Filter {
+        toml::from_str(config).unwrap()
+    }
+
+    fn plain(acct: &str) -> Data {
+        toot(acct, "<p>Hello</p>", "", Some("en"), &[], 0)
+    }
+
+    #[test]
+    fn empty_filter_accepts_everything() {
+        assert!(filter("").accepts(&plain("bob@m.s")));
+    }
+
+    #[test]
+    fn authors() {
+        let only = filter("[only]\nauthors = [\"@Bob@M.S\", \"ann\"]");
+        assert!(only.accepts(&plain("bob@m.s")));
+        assert!(only.accepts(&plain("ann")));
+        assert!(!only.accepts(&plain("carl@m.s")));
+
+        let skip = filter("[[skip]]\nauthors = [\"bob@m.s\"]");
+        assert!(!skip.accepts(&plain("bob@m.s")));
+        assert!(skip.accepts(&plain("ann")));
+    }
+
+    #[test]
+    fn tags() {
+        let only = filter("[only]\ntags = [\"#Rust\", \"mastodon\"]");
+        let tagged = |tags: &[&str]| toot("bob", "", "", None, tags, 0);
+        assert!(only.accepts(&tagged(&["rust"])));
+        assert!(only.accepts(&tagged(&["other", "Mastodon"])));
+        assert!(!only.accepts(&tagged(&["other"])));
+        assert!(!only.accepts(&tagged(&[])));
+    }
+
+    #[test]
+    fn media() {
+        let with_media = toot("bob", "", "", None, &[], 2);
+        let without_media = toot("bob", "", "", None, &[], 0);
+
+        let only_media = filter("[only]\nmedia = true");
+        assert!(only_media.accepts(&with_media));
+        assert!(!only_media.accepts(&without_media));
+
+        let only_text = filter("[only]\nmedia = false");
+        assert!(!only_text.accepts(&with_media));
+        assert!(only_text.accepts(&without_media));
+    }
+
+    #[test]
+    fn languages() {
+        let only = filter("[only]\nlanguages = [\"EN\", \"pt\"]");
+        let written_in = |language| toot("bob", "", "", language, &[], 0);
+        assert!(only.accepts(&written_in(Some("en"))));
+        assert!(only.accepts(&written_in(Some("PT"))));
+        assert!(!only.accepts(&written_in(Some("de"))));
+        // Without a known language, the toot can't be in any of them.
+        assert!(!only.accepts(&written_in(None)));
+    }
+
+    #[test]
+    fn spoilers() {
+        let warned = |spoiler| toot("bob", "", spoiler, None, &[], 0);
+
+        let skip = filter("[[skip]]\nspoiler = \"Politics\"");
+        assert!(!skip.accepts(&warned("uspol, politics")));
+        assert!(skip.accepts(&warned("food")));
+        assert!(skip.accepts(&warned("")));
+
+        // An empty text matches any content warning.
+        let skip_any = filter("[[skip]]\nspoiler = \"\"");
+        assert!(!skip_any.accepts(&warned("food")));
+        assert!(skip_any.accepts(&warned("")));
+    }
+
+    #[test]
+    fn content() {
+        let only = filter("[only]\ncontent = \"(?i)\\\\bhello\\\\b\"");
+        let saying = |content| toot("bob", content, "", None, &[], 0);
+        assert!(only.accepts(&saying("<p>Oh, <strong>HELLO</strong></p>")));
+        assert!(!only.accepts(&saying("<p>Othello</p>")));
+        // The formatting isn't part of the text.
+        let tags = filter("[only]\ncontent = \"strong\"");
+        assert!(!tags.accepts(&saying("<p><strong>bold</strong></p>")));
+    }
+
+    #[test]
+    fn invalid_content_patterns_are_rejected() {
+        assert!(toml::from_str::<Filter>("[only]\ncontent = \"(\"").is_err());
+    }
+
+    #[test]
+    fn conditions_of_a_rule_must_all_match() {
+        let only = filter("[only]\nauthors = [\"bob\"]\nmedia = true");
+        assert!(only.accepts(&toot("bob", "", "", None, &[], 1)));
+        assert!(!only.accepts(&toot("bob", "", "", None, &[], 0)));
+        assert!(!only.accepts(&toot("ann", "", "", None, &[], 1)));
+    }
+
+    #[test]
+    fn only_and_skip_together() {
+        let both = filter(
+            "[only]\nmedia = true\n\n\
+             [[skip]]\nauthors = [\"troll\"]\n\n\
+             [[skip]]\nspoiler = \"spoilers\"",
+        );
+        assert!(both.accepts(&toot("bob", "", "", None, &[], 1)));
+        assert!(!both.accepts(&toot("bob", "", "", None, &[], 0)));
+        assert!(!both.accepts(&toot("troll", "", "", None, &[], 1)));
+        assert!(!both.accepts(&toot("bob", "", "Spoilers!", None, &[], 1)));
+    }
+
+    #[test]
+    fn round_trip() {
+        let config = "[only]\nmedia = true\ncontent = \"a+\"\n\n\
+                      [[skip]]\nauthors = [\"troll\"]\n";
+        let saved = toml::to_string(&filter(config)).unwrap();
+        let loaded = filter(&saved);
+        assert!(loaded.accepts(&toot("bob", "<p>aaa</p>", "", None, &[], 1)));
+        assert!(!loaded.accepts(&toot(
+            "troll",
+            "<p>aaa</p>",
+            "",
+            None,
+            &[],
+            1
+        )));
+    }
+}
@@ -20,6 +20,10 @@ pub mod account;
 pub mod config;
 pub mod errors;
 pub mod favourite;
+pub mod filter;
+pub mod seen;
+pub mod statuses;
+pub mod storage;
 
 use self::errors::ConfigError;
 
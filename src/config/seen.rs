@@ -0,0 +1,233 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::Path;
+use std::path::PathBuf;
+
+use log_derive::logfn;
+use log_derive::logfn_inputs;
+
+use super::config::Config;
+use crate::config::errors::ConfigError;
+use crate::storage::data::Origin;
+use crate::storage::filename::sanitize;
+use crate::storage::store::sha256;
+
+/// Index of the statuses already archived for an account.
+///
+/// The index is a plain file, with one status ID per line, stored in the
+/// `seen` directory alongside the configuration file (in `favourites`,
+/// `bookmarks` or `statuses`, depending on the origin); new IDs are appended
+/// as soon as they are archived, so an interrupted run doesn't lose track of
+/// what was already saved.
+///
+/// The statuses that failed to be archived are kept in a second file, in
+/// `seen/retries`, so the next run looks for them again.
+#[derive(Debug)]
+pub struct Seen {
+    filename: PathBuf,
+    retry_file: PathBuf,
+    ids: HashSet<String>,
+    retries: HashSet<String>,
+}
+
+impl Seen {
+    /// Open the index of the account for toots of the origin (favourites,
+    /// bookmarks and the account's own statuses are tracked separately); if
+    /// it doesn't exist, returns an empty index.
+    #[logfn_inputs(Trace)]
+    pub fn open(account: &str, origin: Origin) -> Result<Self, ConfigError> {
+        let seen = Config::directory()?.join("seen");
+        let kind = match origin {
+            Origin::Favourite => "favourites",
+            Origin::Bookmark => "bookmarks",
+            Origin::Post | Origin::Boost => "statuses",
+        };
+        let name = index_name(account);
+        let filename = seen.join(kind).join(&name);
+        let retry_file = seen.join("retries").join(kind).join(&name);
+        migrate(&seen, account, origin, &filename)?;
+        std::fs::create_dir_all(seen.join(kind))?;
+        std::fs::create_dir_all(seen.join("retries").join(kind))?;
+        log::debug!("Seen index: {:?}", filename);
+
+        Ok(Self {
+            ids: read_ids(&filename)?,
+            retries: read_ids(&retry_file)?,
+            filename,
+            retry_file,
+        })
+    }
+
+    /// Check if the status was already archived.
+    #[logfn(Trace)]
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids.contains(id)
+    }
+
+    /// Mark the status as archived.
+    #[logfn_inputs(Trace)]
+    pub fn insert(&mut self, id: &str) -> Result<(), ConfigError> {
+        if self.ids.insert(id.into()) {
+            let mut fp = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.filename)?;
+            writeln!(fp, "{}", id)?;
+        }
+        if self.retries.remove(id) {
+            self.save_retries()?;
+        }
+        Ok(())
+    }
+
+    /// The statuses that failed to be archived in a previous run.
+    pub fn retries(&self) -> &HashSet<String> {
+        &self.retries
+    }
+
+    /// Mark the status as failed, to be tried again in the next run.
+    #[logfn_inputs(Trace)]
+    pub fn retry_later(&mut self, id: &str) -> Result<(), ConfigError> {
+        if self.retries.insert(id.into()) {
+            self.save_retries()?;
+        }
+        Ok(())
+    }
+
+    /// Stop trying the statuses again (e.g., they are not in the list
+    /// anymore).
+    #[logfn_inputs(Trace)]
+    pub fn forget(&mut self, ids: &HashSet<String>) -> Result<(), ConfigError> {
+        let before = self.retries.len();
+        self.retries.retain(|id| !ids.contains(id));
+        if self.retries.len() != before {
+            self.save_retries()?;
+        }
+        Ok(())
+    }
+
+    fn save_retries(&self) -> Result<(), ConfigError> {
+        if self.retries.is_empty() {
+            return match std::fs::remove_file(&self.retry_file) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(e.into())
+                }
+                _ => Ok(()),
+            };
+        }
+        let mut fp = File::create(&self.retry_file)?;
+        for id in self.retries.iter() {
+            writeln!(fp, "{}", id)?;
+        }
+        Ok(())
+    }
+}
+
+/// The name of the index of the account.
+///
+/// The account alias comes from the user, so it is sanitized, to make sure it
+/// can't point outside the directory; as different aliases may end up with
+/// the same name (`a/b` and `a:b`), a sanitized alias gets part of the hash
+/// of the original one.
+fn index_name(account: &str) -> String {
+    let name = sanitize(account);
+    if name == account {
+        name
+    } else {
+        format!("{}-{}", name, &sha256(account.as_bytes())[..8])
+    }
+}
+
+/// Move the index from where older versions kept it: the favourites were in
+/// `seen` itself, and the alias was used as is.
+fn migrate(
+    seen: &Path,
+    account: &str,
+    origin: Origin,
+    filename: &Path,
+) -> Result<(), ConfigError> {
+    let plain = !account.is_empty()
+        && account != "."
+        && account != ".."
+        && !account.contains(['/', '\\']);
+    let old = match origin {
+        Origin::Favourite => seen.join(account),
+        Origin::Bookmark => seen.join("bookmarks").join(account),
+        Origin::Post | Origin::Boost => seen.join("statuses").join(account),
+    };
+    if !plain || old == filename || !old.is_file() || filename.exists() {
+        return Ok(());
+    }
+
+    log::debug!("Moving seen index {:?} to {:?}", old, filename);
+    // For an account called "favourites", the old index is where the new
+    // directory goes, so it is moved out of the way first.
+    let moving = seen.join(format!(".{}.moving", account));
+    std::fs::rename(&old, &moving)?;
+    std::fs::create_dir_all(filename.parent().unwrap_or(seen))?;
+    std::fs::rename(&moving, filename)?;
+    Ok(())
+}
+
+/// Read the IDs in the file, one per line; if the file doesn't exist, there
+/// are none.
+fn read_ids(filename: &Path) -> Result<HashSet<String>, ConfigError> {
+    match File::open(filename) {
+        Ok(fp) => Ok(BufReader::new(fp)
+            .lines()
+            .collect::<Result<Vec<String>, _>>()?
+            .into_iter()
+            .filter(|line| !line.is_empty())
+            .collect()),
+        Err(_) => Ok(HashSet::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_aliases_are_kept() {
+        assert_eq!(index_name("myaccount"), "myaccount");
+        assert_eq!(index_name("me@mastodon.social"), "me@mastodon.social");
+    }
+
+    #[test]
+    fn aliases_stay_in_the_directory() {
+        for alias in &["../config", "..", "a/b", "a\\b", "/etc/passwd"] {
+            let name = index_name(alias);
+            assert!(!name.contains('/'), "{} -> {}", alias, name);
+            assert!(!name.contains('\\'), "{} -> {}", alias, name);
+            assert!(!name.starts_with('.'), "{} -> {}", alias, name);
+        }
+    }
+
+    #[test]
+    fn sanitized_aliases_do_not_clash() {
+        assert_ne!(index_name("a/b"), index_name("a:b"));
+        assert_ne!(index_name("a/b"), index_name("a_b"));
+        assert_eq!(index_name("a/b"), index_name("a/b"));
+    }
+}
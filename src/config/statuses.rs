@@ -0,0 +1,79 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::io::Write;
+
+use log_derive::logfn;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+
+use crate::config::errors::ConfigError;
+use crate::config::Configurable;
+
+/// Configuration for archiving the statuses of the account itself.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StatusesConfig {
+    /// The last seen status.
+    last: Option<String>,
+    /// Whether replies are archived.
+    pub replies: bool,
+    /// Whether boosts are archived.
+    pub reblogs: bool,
+}
+
+impl StatusesConfig {
+    /// Return the last seen status, or "0" if there is none.
+    pub fn last(&self) -> String {
+        match &self.last {
+            Some(last) => last.to_string(),
+            None => "0".into(),
+        }
+    }
+
+    /// Set the last seen status.
+    pub fn set(&mut self, last: &str) {
+        self.last = Some(last.into());
+    }
+}
+
+/// Ask a yes/no question.
+fn ask(question: &str, default: bool) -> Result<bool, ConfigError> {
+    print!("{} {} ", question, if default { "[Y/n]" } else { "[y/N]" });
+    std::io::stdout().flush().expect("Failed to flush stdout!");
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(match answer.trim().to_lowercase().as_ref() {
+        "y" | "yes" => true,
+        "n" | "no" => false,
+        _ => default,
+    })
+}
+
+impl Configurable for StatusesConfig {
+    #[logfn(Trace)]
+    fn config() -> Result<Self, ConfigError> {
+        let replies = ask("Archive replies?", true)?;
+        let reblogs = ask("Archive boosts?", false)?;
+        Ok(Self {
+            last: None,
+            replies,
+            reblogs,
+        })
+    }
+}
@@ -0,0 +1,77 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+
+use super::filter::Filter;
+use crate::storage::feed::config::FeedConfig;
+use crate::storage::html::config::HtmlConfig;
+use crate::storage::json::config::JsonConfig;
+use crate::storage::markdown::config::MarkdownConfig;
+use crate::storage::org::config::OrgConfig;
+use crate::storage::sqlite::config::SqliteConfig;
+
+/// A storage of an account, identified by its name.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StorageEntry {
+    pub name: String,
+    #[serde(flatten)]
+    pub config: StorageConfig,
+    /// Which toots go to the storage.
+    #[serde(flatten)]
+    pub filter: Filter,
+}
+
+/// The configuration of a storage, by type.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum StorageConfig {
+    Markdown(MarkdownConfig),
+    Org(OrgConfig),
+    Json(JsonConfig),
+    Sqlite(SqliteConfig),
+    Html(HtmlConfig),
+    Feed(FeedConfig),
+}
+
+impl StorageConfig {
+    /// The name of the storage type, as used in the command line.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StorageConfig::Markdown(_) => "markdown",
+            StorageConfig::Org(_) => "org",
+            StorageConfig::Json(_) => "json",
+            StorageConfig::Sqlite(_) => "sqlite",
+            StorageConfig::Html(_) => "html",
+            StorageConfig::Feed(_) => "feed",
+        }
+    }
+
+    /// Where the storage keeps its content.
+    pub fn path(&self) -> &str {
+        match self {
+            StorageConfig::Markdown(config) => &config.path,
+            StorageConfig::Org(config) => &config.path,
+            StorageConfig::Json(config) => &config.path,
+            StorageConfig::Sqlite(config) => &config.path,
+            StorageConfig::Html(config) => &config.path,
+            StorageConfig::Feed(config) => &config.path,
+        }
+    }
+}
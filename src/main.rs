@@ -25,7 +25,11 @@ fn main() {
     env_logger::init();
 
     match args::parse() {
-        Ok(command) => command.execute().unwrap(),
+        Ok(command) => {
+            if let Err(e) = command.execute() {
+                println!("Error: {:?}", e);
+            }
+        }
         Err(e) => println!("Error: {:?}", e),
     }
 }
@@ -17,45 +17,139 @@
 */
 
 use std::convert::From;
-use std::time::Duration;
+use std::path::Path;
 
-use reqwest::Response;
+use serde_derive::Deserialize;
+use serde_json::Value;
+
+use crate::storage::download::discard_partial;
+use crate::storage::download::download;
+use crate::storage::errors::StorageError;
+use crate::storage::filename::decode;
+use crate::storage::filename::sanitize;
 
 #[derive(Debug)]
 pub struct Attachment {
     url: String,
+    remote_url: Option<String>,
+    preview_url: String,
+    description: Option<String>,
+    media_type: String,
+    dimensions: Option<(u64, u64)>,
+}
+
+/// The metadata of an attachment, as sent by the server; Elefren doesn't
+/// expose the size of the media, so we read it from the JSON.
+#[derive(Deserialize)]
+struct Meta {
+    original: Option<Size>,
 }
 
-impl From<&elefren::entities::attachment::Attachment> for Attachment {
-    fn from(origin: &elefren::entities::attachment::Attachment) -> Self {
+#[derive(Deserialize)]
+struct Size {
+    width: Option<u64>,
+    height: Option<u64>,
+}
+
+impl Attachment {
+    /// Convert the attachment parsed by Elefren, along with the JSON it was
+    /// parsed from.
+    pub fn new(
+        origin: &elefren::entities::attachment::Attachment,
+        json: &Value,
+    ) -> Self {
         // XXX basename of the origin.url here
         println!("Found attachment: {}", origin.url);
         Self {
             url: origin.url.to_string(),
+            remote_url: origin.remote_url.clone(),
+            preview_url: origin.preview_url.to_string(),
+            description: origin
+                .description
+                .as_ref()
+                .map(|description| description.trim().to_string())
+                .filter(|description| !description.is_empty()),
+            // Elefren doesn't know all the media types (e.g., audio).
+            media_type: json["type"].as_str().unwrap_or("unknown").into(),
+            dimensions: dimensions(&json["meta"]),
         }
     }
 }
 
+/// Width and height of the media, from the metadata of the attachment.
+fn dimensions(meta: &Value) -> Option<(u64, u64)> {
+    let meta: Meta = serde_json::from_value(meta.clone()).ok()?;
+    let size = meta.original?;
+    Some((size.width?, size.height?))
+}
+
 impl Attachment {
-    pub fn filename(&self) -> String {
-        let mut frags = self.url.rsplitn(2, '/');
+    /// The URL of the attachment in the server.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
 
-        if let Some(path_part) = frags.next() {
-            path_part.split('?').next().unwrap_or(&self.url).to_string()
-        } else {
-            // this is, most of the time, bad (due special characters -- like '?' -- and path)
-            self.url.to_string()
-        }
+    /// The URL of the attachment in its original server, if it came from
+    /// another server.
+    pub fn remote_url(&self) -> Option<&str> {
+        self.remote_url.as_deref()
     }
 
-    // XXX unwrap
-    pub fn download(&self) -> Response {
-        reqwest::Client::builder()
-            .timeout(Duration::from_secs(600))
-            .build()
-            .unwrap()
-            .get(&self.url)
-            .send()
-            .unwrap()
+    /// The URL of the smaller version of the attachment.
+    pub fn preview_url(&self) -> &str {
+        &self.preview_url
+    }
+
+    /// The alt text of the attachment.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// The type of media (image, video, gifv, audio...)
+    pub fn media_type(&self) -> &str {
+        &self.media_type
+    }
+
+    /// Width and height of the media, when the server knows them.
+    pub fn dimensions(&self) -> Option<(u64, u64)> {
+        self.dimensions
+    }
+
+    /// A safe name for the attachment file, based on its URL; the name may not
+    /// have an extension, if the URL doesn't have one.
+    pub fn filename(&self) -> String {
+        let segment = reqwest::Url::parse(&self.url)
+            .ok()
+            .and_then(|url| {
+                url.path_segments().and_then(|mut segments| {
+                    segments.next_back().map(String::from)
+                })
+            })
+            .unwrap_or_default();
+        sanitize(&decode(&segment))
+    }
+
+    /// Download the attachment to the target file, returning its content
+    /// type; if the local copy in the server fails, try the one in the
+    /// original server.
+    pub fn download(
+        &self,
+        target: &Path,
+    ) -> Result<Option<String>, StorageError> {
+        match (download(&self.url, target), &self.remote_url) {
+            (Ok(content_type), _) => Ok(content_type),
+            (Err(e), Some(remote_url)) => {
+                log::debug!("Failed to download {}: {:?}", self.url, e);
+                // Whatever came from the first URL can't be continued with
+                // the content of another one.
+                discard_partial(target)?;
+                println!(
+                    "Downloading {} from {} instead",
+                    self.url, remote_url
+                );
+                download(remote_url, target)
+            }
+            (Err(e), None) => Err(e),
+        }
     }
 }
@@ -18,12 +18,101 @@
 
 use std::convert::From;
 
+use chrono::DateTime;
+use chrono::Utc;
 use elefren::entities::status::Status;
+use elefren::status_builder::Visibility;
 use log_derive::logfn;
 use log_derive::logfn_inputs;
+use serde_json::Value;
 
 use crate::storage::attachment::Attachment;
 
+/// Where the toot came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Origin {
+    /// The toot was favourited.
+    Favourite,
+    /// The toot was bookmarked.
+    Bookmark,
+    /// The toot was posted by the account.
+    Post,
+    /// The toot was boosted by the account.
+    Boost,
+}
+
+impl Origin {
+    /// The name of the origin, as saved by the storages.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Origin::Favourite => "favourite",
+            Origin::Bookmark => "bookmark",
+            Origin::Post => "post",
+            Origin::Boost => "boost",
+        }
+    }
+}
+
+/// A status, as parsed by Elefren, along with the JSON sent by the server,
+/// which also has the fields Elefren doesn't know about.
+#[derive(Debug, Clone)]
+pub struct Toot {
+    pub status: Status,
+    pub json: Value,
+}
+
+impl Toot {
+    pub fn parse(json: Value) -> Result<Self, serde_json::Error> {
+        Ok(Self {
+            status: serde_json::from_value(for_elefren(json.clone()))?,
+            json,
+        })
+    }
+
+    /// The ID of the status.
+    pub fn id(&self) -> &str {
+        &self.status.id
+    }
+
+    /// The boosted toot, if this is a boost.
+    pub fn reblog(&self) -> Option<Toot> {
+        let status = self.status.reblog.as_ref()?;
+        Some(Self {
+            status: status.as_ref().clone(),
+            json: self.json["reblog"].clone(),
+        })
+    }
+}
+
+/// Change the status JSON into something Elefren can parse.
+///
+/// Elefren only knows some of the media types (there is no audio, for
+/// example) and expects every media metadata to have a width and a height,
+/// which audio doesn't have; a single attachment like that makes the whole
+/// status fail. We read the type and the metadata of the attachments from the
+/// JSON anyway, so Elefren gets a generic version of them.
+fn for_elefren(mut json: Value) -> Value {
+    let attachments = json
+        .get_mut("media_attachments")
+        .and_then(Value::as_array_mut);
+    for attachment in attachments.into_iter().flatten() {
+        if let Some(attachment) = attachment.as_object_mut() {
+            let known = matches!(
+                attachment.get("type").and_then(Value::as_str),
+                Some("image") | Some("video") | Some("gifv")
+            );
+            if !known {
+                attachment.insert("type".into(), "unknown".into());
+            }
+            attachment.insert("meta".into(), Value::Null);
+        }
+    }
+    if let Some(reblog) = json.get_mut("reblog").filter(|r| r.is_object()) {
+        *reblog = for_elefren(reblog.take());
+    }
+    json
+}
+
 /// Our data content.
 #[derive(Debug)]
 pub struct Data {
@@ -33,13 +122,41 @@ pub struct Data {
     pub text: String,
     pub attachments: Vec<Attachment>,
     pub source: String,
+    /// When the toot was posted.
+    pub created_at: DateTime<Utc>,
+    /// The name the author displays, instead of the account.
+    pub display_name: String,
+    /// Hashtags in the toot, without the `#`.
+    pub tags: Vec<String>,
+    /// Accounts mentioned in the toot.
+    pub mentions: Vec<String>,
+    /// The language of the toot, if the server knows it.
+    pub language: Option<String>,
+    /// Who can see the toot (public, unlisted, private or direct).
+    pub visibility: String,
+    /// Whether the toot was marked as sensitive.
+    pub sensitive: bool,
+    /// The toot this one replies to.
+    pub in_reply_to: Option<String>,
+    /// The original status, as parsed by Elefren.
+    pub status: Status,
+    /// The original status, as received from the server.
+    pub json: Value,
+    /// The toots this one replies to, oldest first, if the conversation was
+    /// requested.
+    pub ancestors: Vec<Data>,
+    /// The replies to this toot, if the conversation was requested.
+    pub descendants: Vec<Data>,
+    /// Why the toot is being archived.
+    pub origin: Origin,
 }
 
-/// Convert the incoming Status from Elefren to ours.
-impl From<&Status> for Data {
+/// Convert the incoming toot to ours.
+impl From<&Toot> for Data {
     #[logfn(Trace)]
     #[logfn_inputs(Trace)]
-    fn from(origin: &Status) -> Self {
+    fn from(toot: &Toot) -> Self {
+        let origin = &toot.status;
         Self {
             id: origin.id.to_string(),
             account: origin.account.acct.to_string(),
@@ -48,9 +165,176 @@ impl From<&Status> for Data {
             attachments: origin
                 .media_attachments
                 .iter()
-                .map(|attachment| Attachment::from(attachment))
+                .enumerate()
+                .map(|(position, attachment)| {
+                    Attachment::new(
+                        attachment,
+                        &toot.json["media_attachments"][position],
+                    )
+                })
                 .collect(),
             source: origin.url.as_ref().unwrap_or(&String::new()).to_string(),
+            created_at: origin.created_at,
+            display_name: origin.account.display_name.to_string(),
+            tags: origin.tags.iter().map(|tag| tag.name.to_string()).collect(),
+            mentions: origin
+                .mentions
+                .iter()
+                .map(|mention| mention.acct.to_string())
+                .collect(),
+            language: origin.language.clone(),
+            visibility: visibility(origin.visibility).into(),
+            sensitive: origin.sensitive,
+            in_reply_to: origin.in_reply_to_id.clone(),
+            status: origin.clone(),
+            json: toot.json.clone(),
+            ancestors: Vec::new(),
+            descendants: Vec::new(),
+            origin: Origin::Favourite,
         }
     }
 }
+
+impl Data {
+    /// Add the conversation around the toot.
+    pub fn set_context(&mut self, ancestors: &[Toot], descendants: &[Toot]) {
+        self.ancestors = ancestors.iter().map(Data::from).collect();
+        self.descendants = descendants.iter().map(Data::from).collect();
+    }
+}
+
+/// The name of the visibility, as used by the API.
+fn visibility(visibility: Visibility) -> &'static str {
+    match visibility {
+        Visibility::Direct => "direct",
+        Visibility::Private => "private",
+        Visibility::Unlisted => "unlisted",
+        Visibility::Public => "public",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// A status with an audio attachment, as sent by Mastodon.
+    fn audio_status() -> Value {
+        json!({
+            "id": "103270115826048975",
+            "created_at": "2019-12-08T03:48:33.901Z",
+            "in_reply_to_id": null,
+            "in_reply_to_account_id": null,
+            "sensitive": false,
+            "spoiler_text": "",
+            "visibility": "public",
+            "language": "en",
+            "uri": "https://mastodon.social/users/Gargron/statuses/103270115826048975",
+            "url": "https://mastodon.social/@Gargron/103270115826048975",
+            "replies_count": 5,
+            "reblogs_count": 6,
+            "favourites_count": 11,
+            "favourited": false,
+            "reblogged": false,
+            "muted": false,
+            "bookmarked": false,
+            "content": "<p>Lofi beats to study to</p>",
+            "reblog": null,
+            "application": {"name": "Web", "website": null},
+            "account": {
+                "id": "1",
+                "username": "Gargron",
+                "acct": "Gargron",
+                "display_name": "Eugen",
+                "locked": false,
+                "bot": false,
+                "created_at": "2016-03-16T14:34:26.392Z",
+                "note": "<p>Developer of Mastodon</p>",
+                "url": "https://mastodon.social/@Gargron",
+                "avatar": "https://files.mastodon.social/accounts/avatars/000/000/001/original/d96d39a0abb45b92.jpg",
+                "avatar_static": "https://files.mastodon.social/accounts/avatars/000/000/001/original/d96d39a0abb45b92.jpg",
+                "header": "https://files.mastodon.social/accounts/headers/000/000/001/original/c91b871f294ea63e.png",
+                "header_static": "https://files.mastodon.social/accounts/headers/000/000/001/original/c91b871f294ea63e.png",
+                "followers_count": 322930,
+                "following_count": 459,
+                "statuses_count": 61323,
+                "last_status_at": "2019-12-10T08:14:44.811Z",
+                "emojis": [],
+                "fields": []
+            },
+            "media_attachments": [{
+                "id": "21165404",
+                "type": "audio",
+                "url": "https://files.mastodon.social/media_attachments/files/021/165/404/original/a7d45a7c1b9b1a4f.mp3",
+                "preview_url": "https://files.mastodon.social/media_attachments/files/021/165/404/small/a7d45a7c1b9b1a4f.png",
+                "remote_url": null,
+                "text_url": "https://mastodon.social/media/5O4uILClVqBWx0NNgvo",
+                "meta": {
+                    "length": "0:06:42.86",
+                    "duration": 402.86,
+                    "audio_encode": "mp3",
+                    "audio_bitrate": "44100 Hz",
+                    "audio_channels": "stereo",
+                    "original": {"duration": 402.860408, "bitrate": 166290}
+                },
+                "description": "Lofi beats",
+                "blurhash": null
+            }],
+            "mentions": [],
+            "tags": [],
+            "emojis": [],
+            "card": null,
+            "poll": null
+        })
+    }
+
+    #[test]
+    fn audio_attachments() {
+        let toot = Toot::parse(audio_status()).unwrap();
+        let data = Data::from(&toot);
+        let audio = &data.attachments[0];
+        assert_eq!(audio.media_type(), "audio");
+        assert_eq!(audio.dimensions(), None);
+        assert_eq!(audio.description(), Some("Lofi beats"));
+        // The JSON is kept as the server sent it.
+        assert_eq!(data.json, audio_status());
+    }
+
+    #[test]
+    fn boosted_audio_attachments() {
+        let mut boost = audio_status();
+        boost["id"] = "103270115826048976".into();
+        boost["media_attachments"] = json!([]);
+        boost["reblog"] = audio_status();
+
+        let toot = Toot::parse(boost).unwrap();
+        let boosted = Data::from(&toot.reblog().unwrap());
+        assert_eq!(boosted.id, "103270115826048975");
+        assert_eq!(boosted.attachments[0].media_type(), "audio");
+    }
+
+    #[test]
+    fn image_dimensions() {
+        let mut status = audio_status();
+        status["media_attachments"][0]["type"] = "image".into();
+        status["media_attachments"][0]["meta"] = json!({
+            "original": {
+                "width": 640,
+                "height": 480,
+                "size": "640x480",
+                "aspect": 1.3333333333333333
+            },
+            "small": {
+                "width": 461,
+                "height": 346,
+                "size": "461x346",
+                "aspect": 1.3323699421965318
+            }
+        });
+
+        let data = Data::from(&Toot::parse(status).unwrap());
+        assert_eq!(data.attachments[0].media_type(), "image");
+        assert_eq!(data.attachments[0].dimensions(), Some((640, 480)));
+    }
+}
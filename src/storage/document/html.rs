@@ -0,0 +1,172 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::Block;
+use super::Document;
+use super::Inline;
+
+/// Render the document as HTML.
+///
+/// Only the elements we know are produced, and only links to web (or mail)
+/// addresses are kept, so the result is safe to be embedded in a page, no
+/// matter what the server sent.
+pub fn render(document: &Document) -> String {
+    blocks(&document.blocks)
+}
+
+/// The text of the document, without any formatting, in a single line.
+pub fn text(document: &Document) -> String {
+    let mut result = String::new();
+    for block in document.blocks.iter() {
+        block_text(block, &mut result);
+    }
+    result.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+fn blocks(blocks: &[Block]) -> String {
+    blocks.iter().map(block).collect::<Vec<String>>().join("\n")
+}
+
+fn block(block: &Block) -> String {
+    match block {
+        Block::Paragraph(content) => format!("<p>{}</p>", inlines(content)),
+        Block::Quote(content) => {
+            format!("<blockquote>\n{}\n</blockquote>", blocks(content))
+        }
+        Block::List { ordered, items } => {
+            let tag = if *ordered { "ol" } else { "ul" };
+            let items = items
+                .iter()
+                .map(|item| format!("<li>{}</li>", blocks(item)))
+                .collect::<Vec<String>>()
+                .join("\n");
+            format!("<{tag}>\n{}\n</{tag}>", items, tag = tag)
+        }
+        Block::Code(code) => {
+            format!("<pre><code>{}</code></pre>", escape(code))
+        }
+    }
+}
+
+fn inlines(inlines: &[Inline]) -> String {
+    inlines.iter().map(inline).collect()
+}
+
+fn inline(inline: &Inline) -> String {
+    match inline {
+        Inline::Text(text) => escape(text),
+        Inline::LineBreak => "<br>\n".into(),
+        Inline::Link { url, text } => link(url, &escape(text)),
+        Inline::Mention { url, name } => {
+            link(url, &format!("@{}", escape(name)))
+        }
+        Inline::Hashtag { url, name } => {
+            link(url, &format!("#{}", escape(name)))
+        }
+        Inline::Code(code) => format!("<code>{}</code>", escape(code)),
+        Inline::Emphasis(content) => format!("<em>{}</em>", inlines(content)),
+        Inline::Strong(content) => {
+            format!("<strong>{}</strong>", inlines(content))
+        }
+        Inline::Emoji { shortcode, url } if is_safe(url) => format!(
+            "<img class=\"emoji\" src=\"{}\" alt=\":{}:\" title=\":{}:\">",
+            escape(url),
+            escape(shortcode),
+            escape(shortcode)
+        ),
+        Inline::Emoji { shortcode, .. } => format!(":{}:", escape(shortcode)),
+    }
+}
+
+fn block_text(block: &Block, result: &mut String) {
+    match block {
+        Block::Paragraph(content) => inlines_text(content, result),
+        Block::Quote(content) => {
+            for block in content.iter() {
+                block_text(block, result);
+            }
+        }
+        Block::List { items, .. } => {
+            for block in items.iter().flatten() {
+                block_text(block, result);
+            }
+        }
+        Block::Code(code) => result.push_str(code),
+    }
+    result.push(' ');
+}
+
+fn inlines_text(inlines: &[Inline], result: &mut String) {
+    for inline in inlines.iter() {
+        match inline {
+            Inline::Text(text) | Inline::Code(text) => result.push_str(text),
+            Inline::LineBreak => result.push(' '),
+            Inline::Link { text, .. } => result.push_str(text),
+            Inline::Mention { name, .. } => {
+                result.push('@');
+                result.push_str(name);
+            }
+            Inline::Hashtag { name, .. } => {
+                result.push('#');
+                result.push_str(name);
+            }
+            Inline::Emphasis(content) | Inline::Strong(content) => {
+                inlines_text(content, result)
+            }
+            Inline::Emoji { shortcode, .. } => {
+                result.push(':');
+                result.push_str(shortcode);
+                result.push(':');
+            }
+        }
+    }
+}
+
+/// A link, if the address is safe to follow; otherwise, just the text.
+fn link(url: &str, text: &str) -> String {
+    if is_safe(url) {
+        format!("<a href=\"{}\">{}</a>", escape(url), text)
+    } else {
+        text.into()
+    }
+}
+
+/// Whether the address points to a web page (or an email), and not to
+/// something that could run code, like `javascript:`.
+pub fn is_safe(url: &str) -> bool {
+    let url = url.trim().to_lowercase();
+    url.starts_with("https://")
+        || url.starts_with("http://")
+        || url.starts_with("mailto:")
+}
+
+/// Escape the characters that HTML would consider markup.
+pub fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
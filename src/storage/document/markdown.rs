@@ -0,0 +1,171 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::Block;
+use super::Document;
+use super::Inline;
+
+/// Render the document as Markdown.
+pub fn render(document: &Document) -> String {
+    blocks(&document.blocks)
+}
+
+fn blocks(blocks: &[Block]) -> String {
+    blocks
+        .iter()
+        .map(block)
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+fn block(block: &Block) -> String {
+    match block {
+        Block::Paragraph(content) => inlines(content),
+        Block::Quote(content) => prefix_lines(&blocks(content), "> ", "> "),
+        Block::List { ordered, items } => items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| {
+                let marker = if *ordered {
+                    format!("{}. ", index + 1)
+                } else {
+                    "- ".into()
+                };
+                let indent = " ".repeat(marker.len());
+                prefix_lines(&blocks(item), &marker, &indent)
+            })
+            .collect::<Vec<String>>()
+            .join("\n"),
+        Block::Code(code) => format!("```\n{}\n```", code),
+    }
+}
+
+fn inlines(inlines: &[Inline]) -> String {
+    inlines.iter().map(inline).collect()
+}
+
+fn inline(inline: &Inline) -> String {
+    match inline {
+        Inline::Text(text) => escape(text),
+        Inline::LineBreak => "  \n".into(),
+        Inline::Link { url, text } => {
+            format!("[{}]({})", escape(text), destination(url))
+        }
+        Inline::Mention { url, name } => {
+            format!("[@{}]({})", escape(name), destination(url))
+        }
+        Inline::Hashtag { url, name } => {
+            format!("[#{}]({})", escape(name), destination(url))
+        }
+        Inline::Code(code) => format!("`{}`", code),
+        Inline::Emphasis(content) => format!("_{}_", inlines(content)),
+        Inline::Strong(content) => format!("**{}**", inlines(content)),
+        Inline::Emoji { shortcode, .. } => format!(":{}:", shortcode),
+    }
+}
+
+/// Add a prefix to the first line and an indentation to the others.
+fn prefix_lines(text: &str, first: &str, others: &str) -> String {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| {
+            let prefix = if index == 0 { first } else { others };
+            format!("{}{}", prefix, line).trim_end().to_string()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Escape the characters that Markdown would consider formatting.
+pub fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\`*_[]<>".contains(c) {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Encode the characters that would end the address of a link (or make it
+/// something else), like parenthesis and spaces.
+fn destination(url: &str) -> String {
+    let mut result = String::with_capacity(url.len());
+    for c in url.chars() {
+        match c {
+            '(' | ')' | '<' | '>' | '\\' | ' ' => {
+                result.push_str(&format!("%{:02X}", c as u32))
+            }
+            c if c.is_whitespace() || c.is_control() => {
+                let mut buffer = [0; 4];
+                for byte in c.encode_utf8(&mut buffer).bytes() {
+                    result.push_str(&format!("%{:02X}", byte));
+                }
+            }
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn markdown(html: &str) -> String {
+        render(&Document::parse(html, &[]))
+    }
+
+    #[test]
+    fn links() {
+        assert_eq!(
+            markdown(r#"<p><a href="https://example.com/page">a page</a></p>"#),
+            "[a page](https://example.com/page)"
+        );
+    }
+
+    #[test]
+    fn mentions_and_hashtags() {
+        let html = r##"<p><span class="h-card"><a href="https://m.s/@bob" class="u-url mention">@<span>bob</span></a></span> likes <a href="https://m.s/tags/rust" class="mention hashtag" rel="tag">#<span>rust</span></a></p>"##;
+        assert_eq!(
+            markdown(html),
+            "[@bob](https://m.s/@bob) likes [#rust](https://m.s/tags/rust)"
+        );
+    }
+
+    #[test]
+    fn escaped_addresses() {
+        let html = r#"<p><a href="https://en.wikipedia.org/wiki/Rust_(programming_language)">Rust (language)</a></p>"#;
+        assert_eq!(
+            markdown(html),
+            "[Rust (language)](https://en.wikipedia.org/wiki/Rust_%28programming_language%29)"
+        );
+        let html = r#"<p><a href="https://example.com/a b">x</a></p>"#;
+        assert_eq!(markdown(html), "[x](https://example.com/a%20b)");
+    }
+
+    #[test]
+    fn escaped_text() {
+        assert_eq!(
+            markdown(r#"<p><a href="https://x.y">[*not* a link]</a></p>"#),
+            "[\\[\\*not\\* a link\\]](https://x.y)"
+        );
+        assert_eq!(markdown("<p>2 * 3_4 <b>x</b></p>"), "2 \\* 3\\_4 **x**");
+    }
+}
@@ -0,0 +1,129 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! A small representation of the content of a toot.
+//!
+//! The HTML produced by Mastodon is parsed once into a [`Document`], and each
+//! storage uses one of the renderers to produce its own format, so every
+//! backend sees the same links, mentions, hashtags and line breaks.
+
+pub mod html;
+pub mod markdown;
+pub mod org;
+mod parser;
+
+use crate::storage::data::Data;
+
+/// The content of a toot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub blocks: Vec<Block>,
+}
+
+/// Block elements, which are always separated from each other.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    /// A paragraph of text.
+    Paragraph(Vec<Inline>),
+    /// A quote, with its own blocks.
+    Quote(Vec<Block>),
+    /// A list; each item has its own blocks.
+    List {
+        ordered: bool,
+        items: Vec<Vec<Block>>,
+    },
+    /// Preformatted text.
+    Code(String),
+}
+
+/// Elements inside a paragraph.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inline {
+    /// Plain text.
+    Text(String),
+    /// A forced line break.
+    LineBreak,
+    /// A link to somewhere else, with the text displayed for it.
+    Link { url: String, text: String },
+    /// A mention to another user; the name doesn't have the `@` prefix.
+    Mention { url: String, name: String },
+    /// A hashtag; the name doesn't have the `#` prefix.
+    Hashtag { url: String, name: String },
+    /// Inline code.
+    Code(String),
+    /// Emphasized text.
+    Emphasis(Vec<Inline>),
+    /// Strong text.
+    Strong(Vec<Inline>),
+    /// A custom emoji from the server.
+    Emoji { shortcode: String, url: String },
+}
+
+impl Document {
+    /// Parse the HTML of a toot; custom emojis are recognized by their
+    /// shortcodes.
+    pub fn parse(html: &str, emojis: &[(String, String)]) -> Self {
+        Self {
+            blocks: parser::parse(html, emojis),
+        }
+    }
+}
+
+impl From<&Data> for Document {
+    fn from(data: &Data) -> Self {
+        let emojis: Vec<(String, String)> = data
+            .status
+            .emojis
+            .iter()
+            .map(|emoji| (emoji.shortcode.to_string(), emoji.url.to_string()))
+            .collect();
+        Document::parse(&data.text, &emojis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A toot with a bit of everything, as sent by Mastodon.
+    const TOOT: &str = r##"<p>Hi <span class="h-card"><a href="https://m.s/@bob" class="u-url mention">@<span>bob</span></a></span>, see <a href="https://example.com/x_(y)" rel="nofollow noopener" target="_blank"><span class="invisible">https://</span><span class="">example.com/x_(y)</span><span class="invisible"></span></a> <a href="https://m.s/tags/rust" class="mention hashtag" rel="tag">#<span>rust</span></a></p><p>Second<br>line</p>"##;
+
+    #[test]
+    fn every_format_has_the_same_content() {
+        let document = Document::parse(TOOT, &[]);
+        assert_eq!(
+            markdown::render(&document),
+            "Hi [@bob](https://m.s/@bob), see \
+             [example.com/x\\_(y)](https://example.com/x_%28y%29) \
+             [#rust](https://m.s/tags/rust)\n\nSecond  \nline"
+        );
+        assert_eq!(
+            org::render(&document),
+            "Hi [[https://m.s/@bob][@bob]], see \
+             [[https://example.com/x_(y)][example.com/x_(y)]] \
+             [[https://m.s/tags/rust][#rust]]\n\nSecond\\\\\nline"
+        );
+        assert_eq!(
+            html::render(&document),
+            "<p>Hi <a href=\"https://m.s/@bob\">@bob</a>, see \
+             <a href=\"https://example.com/x_(y)\">example.com/x_(y)</a> \
+             <a href=\"https://m.s/tags/rust\">#rust</a></p>\n\
+             <p>Second<br>\nline</p>"
+        );
+    }
+}
@@ -0,0 +1,165 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::Block;
+use super::Document;
+use super::Inline;
+
+/// Render the document as Org.
+pub fn render(document: &Document) -> String {
+    blocks(&document.blocks)
+}
+
+fn blocks(blocks: &[Block]) -> String {
+    blocks
+        .iter()
+        .map(block)
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+fn block(block: &Block) -> String {
+    match block {
+        Block::Paragraph(content) => inlines(content),
+        Block::Quote(content) => {
+            format!("#+begin_quote\n{}\n#+end_quote", blocks(content))
+        }
+        Block::List { ordered, items } => items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| {
+                let marker = if *ordered {
+                    format!("{}. ", index + 1)
+                } else {
+                    "- ".into()
+                };
+                let indent = " ".repeat(marker.len());
+                blocks(item)
+                    .lines()
+                    .enumerate()
+                    .map(|(line_number, line)| {
+                        let prefix =
+                            if line_number == 0 { &marker } else { &indent };
+                        format!("{}{}", prefix, line).trim_end().to_string()
+                    })
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            })
+            .collect::<Vec<String>>()
+            .join("\n"),
+        Block::Code(code) => {
+            format!("#+begin_example\n{}\n#+end_example", code)
+        }
+    }
+}
+
+fn inlines(inlines: &[Inline]) -> String {
+    inlines.iter().map(inline).collect()
+}
+
+fn inline(inline: &Inline) -> String {
+    match inline {
+        Inline::Text(text) => text.to_string(),
+        Inline::LineBreak => "\\\\\n".into(),
+        Inline::Link { url, text } => link(url, text),
+        Inline::Mention { url, name } => link(url, &format!("@{}", name)),
+        Inline::Hashtag { url, name } => link(url, &format!("#{}", name)),
+        Inline::Code(code) => format!("~{}~", code),
+        Inline::Emphasis(content) => format!("/{}/", inlines(content)),
+        Inline::Strong(content) => format!("*{}*", inlines(content)),
+        Inline::Emoji { shortcode, .. } => format!(":{}:", shortcode),
+    }
+}
+
+/// A link, escaped the same way Org does it: brackets in the address (and
+/// the backslashes before them) get a backslash, and a zero-width space
+/// keeps the brackets in the description from closing the link.
+fn link(url: &str, description: &str) -> String {
+    let mut address = String::with_capacity(url.len());
+    let mut backslashes = 0;
+    for c in url.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        if c == '[' || c == ']' {
+            address.push_str(&"\\".repeat(backslashes * 2 + 1));
+        } else {
+            address.push_str(&"\\".repeat(backslashes));
+        }
+        backslashes = 0;
+        address.push(c);
+    }
+    address.push_str(&"\\".repeat(backslashes * 2));
+
+    let mut text = String::with_capacity(description.len());
+    let mut chars = description.chars().peekable();
+    while let Some(c) = chars.next() {
+        text.push(c);
+        if c == ']' && matches!(chars.peek(), Some(']') | None) {
+            text.push('\u{200B}');
+        }
+    }
+    format!("[[{}][{}]]", address, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn org(html: &str) -> String {
+        render(&Document::parse(html, &[]))
+    }
+
+    #[test]
+    fn links() {
+        assert_eq!(
+            org(r#"<p><a href="https://example.com/page">a page</a></p>"#),
+            "[[https://example.com/page][a page]]"
+        );
+    }
+
+    #[test]
+    fn mentions_and_hashtags() {
+        let html = r##"<p><span class="h-card"><a href="https://m.s/@bob" class="u-url mention">@<span>bob</span></a></span> likes <a href="https://m.s/tags/rust" class="mention hashtag" rel="tag">#<span>rust</span></a></p>"##;
+        assert_eq!(
+            org(html),
+            "[[https://m.s/@bob][@bob]] likes [[https://m.s/tags/rust][#rust]]"
+        );
+    }
+
+    #[test]
+    fn escaped_addresses() {
+        assert_eq!(
+            org(r#"<p><a href="https://x.y/?a[]=1">x</a></p>"#),
+            "[[https://x.y/?a\\[\\]=1][x]]"
+        );
+        assert_eq!(
+            org(r#"<p><a href="https://x.y/a\[b\">x</a></p>"#),
+            "[[https://x.y/a\\\\\\[b\\\\][x]]"
+        );
+    }
+
+    #[test]
+    fn escaped_descriptions() {
+        assert_eq!(
+            org(r#"<p><a href="https://x.y">[[a]] b]</a></p>"#),
+            "[[https://x.y][[[a]\u{200B}] b]\u{200B}]]"
+        );
+    }
+}
@@ -0,0 +1,416 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::default::Default;
+
+use html5ever::parse_document;
+use html5ever::tendril::TendrilSink;
+use markup5ever_rcdom::Handle;
+use markup5ever_rcdom::NodeData;
+use markup5ever_rcdom::RcDom;
+
+use super::Block;
+use super::Inline;
+
+/// Parse the HTML into blocks.
+pub fn parse(html: &str, emojis: &[(String, String)]) -> Vec<Block> {
+    let dom = parse_document(RcDom::default(), Default::default())
+        .from_utf8()
+        .read_from(&mut html.as_bytes());
+    match dom {
+        Ok(dom) => {
+            let parser = Parser { emojis };
+            parser.blocks(&dom.document)
+        }
+        Err(e) => {
+            // Reading from memory doesn't really fail, but just in case, we
+            // keep the content as it is.
+            log::debug!("Failed to parse HTML: {:?}", e);
+            vec![Block::Paragraph(vec![Inline::Text(html.into())])]
+        }
+    }
+}
+
+/// The name of the element, if the node is an element.
+fn tag(node: &Handle) -> Option<String> {
+    match node.data {
+        NodeData::Element { ref name, .. } => Some(name.local.to_string()),
+        _ => None,
+    }
+}
+
+/// The value of an attribute of an element.
+fn attribute(node: &Handle, attribute: &str) -> Option<String> {
+    match node.data {
+        NodeData::Element { ref attrs, .. } => attrs
+            .borrow()
+            .iter()
+            .find(|attr| attr.name.local.to_string() == attribute)
+            .map(|attr| attr.value.to_string()),
+        _ => None,
+    }
+}
+
+/// Check if the element has the class.
+fn has_class(node: &Handle, class: &str) -> bool {
+    attribute(node, "class")
+        .map(|classes| classes.split_whitespace().any(|name| name == class))
+        .unwrap_or(false)
+}
+
+/// All the text inside the node.
+fn text_content(node: &Handle) -> String {
+    match node.data {
+        NodeData::Text { ref contents } => contents.borrow().to_string(),
+        _ => node.children.borrow().iter().map(text_content).collect(),
+    }
+}
+
+/// The text of a link, as displayed by Mastodon: the parts of the URL marked
+/// as "invisible" are dropped and the ones marked as "ellipsis" are cut.
+fn visible_text(node: &Handle) -> String {
+    match node.data {
+        NodeData::Text { ref contents } => contents.borrow().to_string(),
+        NodeData::Element { .. } if has_class(node, "invisible") => {
+            String::new()
+        }
+        NodeData::Element { .. } if has_class(node, "ellipsis") => {
+            format!("{}...", text_content(node))
+        }
+        _ => node.children.borrow().iter().map(visible_text).collect(),
+    }
+}
+
+/// Check if there is anything besides whitespace in the inlines.
+fn is_blank(inlines: &[Inline]) -> bool {
+    inlines.iter().all(|inline| match inline {
+        Inline::Text(text) => text.trim().is_empty(),
+        Inline::LineBreak => true,
+        _ => false,
+    })
+}
+
+/// Remove the line breaks and whitespace around the inlines.
+fn trim(mut inlines: Vec<Inline>) -> Vec<Inline> {
+    while let Some(Inline::LineBreak) = inlines.last() {
+        inlines.pop();
+    }
+    while let Some(Inline::LineBreak) = inlines.first() {
+        inlines.remove(0);
+    }
+    if let Some(Inline::Text(text)) = inlines.first_mut() {
+        *text = text.trim_start().to_string();
+    }
+    if let Some(Inline::Text(text)) = inlines.last_mut() {
+        *text = text.trim_end().to_string();
+    }
+    inlines
+}
+
+struct Parser<'a> {
+    emojis: &'a [(String, String)],
+}
+
+impl Parser<'_> {
+    /// Convert the children of the node into blocks; inline content outside
+    /// a block element becomes a paragraph.
+    fn blocks(&self, node: &Handle) -> Vec<Block> {
+        let mut result = Vec::new();
+        let mut pending = Vec::new();
+        for child in node.children.borrow().iter() {
+            match tag(child).as_deref() {
+                Some("head") => {}
+                Some("html") | Some("body") | Some("div") => {
+                    self.flush(&mut pending, &mut result);
+                    result.extend(self.blocks(child));
+                }
+                Some("p") => {
+                    self.flush(&mut pending, &mut result);
+                    pending = self.inlines(child);
+                    self.flush(&mut pending, &mut result);
+                }
+                Some("blockquote") => {
+                    self.flush(&mut pending, &mut result);
+                    result.push(Block::Quote(self.blocks(child)));
+                }
+                Some(list @ "ul") | Some(list @ "ol") => {
+                    self.flush(&mut pending, &mut result);
+                    let items = child
+                        .children
+                        .borrow()
+                        .iter()
+                        .filter(|item| tag(item).as_deref() == Some("li"))
+                        .map(|item| self.blocks(item))
+                        .collect();
+                    result.push(Block::List {
+                        ordered: list == "ol",
+                        items,
+                    });
+                }
+                Some("pre") => {
+                    self.flush(&mut pending, &mut result);
+                    let code = text_content(child);
+                    result.push(Block::Code(code.trim_end().into()));
+                }
+                _ => pending.extend(self.inline(child)),
+            }
+        }
+        self.flush(&mut pending, &mut result);
+        result
+    }
+
+    /// Move the pending inlines to a new paragraph.
+    fn flush(&self, pending: &mut Vec<Inline>, result: &mut Vec<Block>) {
+        let inlines = std::mem::take(pending);
+        if !is_blank(&inlines) {
+            result.push(Block::Paragraph(trim(inlines)));
+        }
+    }
+
+    /// Convert the children of the node into inlines.
+    fn inlines(&self, node: &Handle) -> Vec<Inline> {
+        node.children
+            .borrow()
+            .iter()
+            .flat_map(|child| self.inline(child))
+            .collect()
+    }
+
+    /// Convert a single node into inlines.
+    fn inline(&self, node: &Handle) -> Vec<Inline> {
+        match node.data {
+            NodeData::Text { ref contents } => {
+                self.text(&contents.borrow().to_string())
+            }
+            NodeData::Element { .. } => match tag(node).as_deref() {
+                Some("br") => vec![Inline::LineBreak],
+                Some("a") => vec![self.anchor(node)],
+                Some("code") => vec![Inline::Code(text_content(node))],
+                Some("em") | Some("i") => {
+                    vec![Inline::Emphasis(self.inlines(node))]
+                }
+                Some("strong") | Some("b") => {
+                    vec![Inline::Strong(self.inlines(node))]
+                }
+                Some("span") if has_class(node, "invisible") => vec![],
+                Some("span") if has_class(node, "ellipsis") => {
+                    let mut inlines = self.inlines(node);
+                    inlines.push(Inline::Text("...".into()));
+                    inlines
+                }
+                _ => self.inlines(node),
+            },
+            _ => self.inlines(node),
+        }
+    }
+
+    /// Links can be mentions, hashtags or just links.
+    fn anchor(&self, node: &Handle) -> Inline {
+        let url = attribute(node, "href").unwrap_or_default();
+        let rel = attribute(node, "rel").unwrap_or_default();
+        let text = visible_text(node);
+        if has_class(node, "hashtag")
+            || rel.split_whitespace().any(|r| r == "tag")
+        {
+            Inline::Hashtag {
+                url,
+                name: text.trim_start_matches('#').into(),
+            }
+        } else if has_class(node, "mention") {
+            Inline::Mention {
+                url,
+                name: text.trim_start_matches('@').into(),
+            }
+        } else {
+            Inline::Link { url, text }
+        }
+    }
+
+    /// Split the text around custom emojis.
+    fn text(&self, text: &str) -> Vec<Inline> {
+        let mut result = Vec::new();
+        let mut rest = text;
+        while let Some((position, shortcode, url)) = self.next_emoji(rest) {
+            if position > 0 {
+                result.push(Inline::Text(rest[..position].into()));
+            }
+            result.push(Inline::Emoji {
+                shortcode: shortcode.into(),
+                url: url.into(),
+            });
+            rest = &rest[position + shortcode.len() + 2..];
+        }
+        if !rest.is_empty() {
+            result.push(Inline::Text(rest.into()));
+        }
+        result
+    }
+
+    /// Find the first custom emoji in the text.
+    fn next_emoji<'b>(
+        &'b self,
+        text: &str,
+    ) -> Option<(usize, &'b str, &'b str)> {
+        self.emojis
+            .iter()
+            .filter_map(|(shortcode, url)| {
+                text.find(&format!(":{}:", shortcode)).map(|position| {
+                    (position, shortcode.as_str(), url.as_str())
+                })
+            })
+            .min_by_key(|(position, _, _)| *position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> Inline {
+        Inline::Text(text.into())
+    }
+
+    #[test]
+    fn paragraphs() {
+        assert_eq!(
+            parse("<p>Hello</p><p>World</p>", &[]),
+            vec![
+                Block::Paragraph(vec![text("Hello")]),
+                Block::Paragraph(vec![text("World")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn text_outside_paragraphs() {
+        assert_eq!(
+            parse("loose <b>text</b>", &[]),
+            vec![Block::Paragraph(vec![
+                text("loose "),
+                Inline::Strong(vec![text("text")]),
+            ])]
+        );
+    }
+
+    #[test]
+    fn line_breaks() {
+        assert_eq!(
+            parse("<p><br>one<br>two<br /></p>", &[]),
+            vec![Block::Paragraph(vec![
+                text("one"),
+                Inline::LineBreak,
+                text("two"),
+            ])]
+        );
+    }
+
+    #[test]
+    fn mentions() {
+        let html = r#"<p><span class="h-card"><a href="https://m.s/@bob" class="u-url mention">@<span>bob</span></a></span> hi</p>"#;
+        assert_eq!(
+            parse(html, &[]),
+            vec![Block::Paragraph(vec![
+                Inline::Mention {
+                    url: "https://m.s/@bob".into(),
+                    name: "bob".into(),
+                },
+                text(" hi"),
+            ])]
+        );
+    }
+
+    #[test]
+    fn hashtags() {
+        let html = r##"<p><a href="https://m.s/tags/rust" class="mention hashtag" rel="tag">#<span>rust</span></a></p>"##;
+        assert_eq!(
+            parse(html, &[]),
+            vec![Block::Paragraph(vec![Inline::Hashtag {
+                url: "https://m.s/tags/rust".into(),
+                name: "rust".into(),
+            }])]
+        );
+    }
+
+    #[test]
+    fn links_show_only_the_visible_text() {
+        let html = r#"<p><a href="https://example.com/a/very/long/path" rel="nofollow noopener" target="_blank"><span class="invisible">https://</span><span class="ellipsis">example.com/a/very</span><span class="invisible">/long/path</span></a></p>"#;
+        assert_eq!(
+            parse(html, &[]),
+            vec![Block::Paragraph(vec![Inline::Link {
+                url: "https://example.com/a/very/long/path".into(),
+                text: "example.com/a/very...".into(),
+            }])]
+        );
+    }
+
+    #[test]
+    fn custom_emojis() {
+        let emojis =
+            vec![("blob".to_string(), "https://m.s/blob.png".to_string())];
+        assert_eq!(
+            parse("<p>hi :blob: there :other:</p>", &emojis),
+            vec![Block::Paragraph(vec![
+                text("hi "),
+                Inline::Emoji {
+                    shortcode: "blob".into(),
+                    url: "https://m.s/blob.png".into(),
+                },
+                text(" there :other:"),
+            ])]
+        );
+    }
+
+    #[test]
+    fn nested_blocks() {
+        let html = "<blockquote><p>quoted <strong>bold <em>both</em></strong></p></blockquote>\
+                    <ol><li>one</li><li><p>two</p></li></ol>";
+        assert_eq!(
+            parse(html, &[]),
+            vec![
+                Block::Quote(vec![Block::Paragraph(vec![
+                    text("quoted "),
+                    Inline::Strong(vec![
+                        text("bold "),
+                        Inline::Emphasis(vec![text("both")]),
+                    ]),
+                ])]),
+                Block::List {
+                    ordered: true,
+                    items: vec![
+                        vec![Block::Paragraph(vec![text("one")])],
+                        vec![Block::Paragraph(vec![text("two")])],
+                    ],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn code() {
+        assert_eq!(
+            parse(
+                "<pre><code>let x = 1;\n</code></pre><p>use <code>x</code></p>",
+                &[]
+            ),
+            vec![
+                Block::Code("let x = 1;".into()),
+                Block::Paragraph(vec![text("use "), Inline::Code("x".into())]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,219 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use chrono::DateTime;
+use chrono::Utc;
+use reqwest::header::CONTENT_RANGE;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::header::RANGE;
+use reqwest::header::RETRY_AFTER;
+use reqwest::Client;
+use reqwest::Response;
+use reqwest::StatusCode;
+
+use crate::storage::errors::StorageError;
+
+/// How many times we try to download a file before giving up.
+const ATTEMPTS: u32 = 5;
+
+/// How long to wait before the first retry; it doubles on every retry.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+
+/// The longest we wait between retries, even if the server asks for more.
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// The client used for all downloads, so connections can be reused.
+static CLIENT: OnceLock<Client> = OnceLock::new();
+
+fn client() -> Result<&'static Client, StorageError> {
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+    let client = Client::builder()
+        .timeout(Duration::from_secs(600))
+        .build()?;
+    Ok(CLIENT.get_or_init(|| client))
+}
+
+/// What to do after a failed attempt.
+enum Failure {
+    /// Try again, after waiting (at least) the given time, if the server
+    /// said so.
+    Retry(Option<Duration>, StorageError),
+    /// There is no point in trying again.
+    Fail(StorageError),
+}
+
+/// Download the URL to the target file, returning the content type sent by
+/// the server.
+///
+/// The content is written to `<target>.part` and only renamed to the target
+/// when complete; if the `.part` file already exists (e.g., a previous run
+/// was interrupted), the download continues from where it stopped. Server
+/// errors and timeouts are retried, with an exponential backoff.
+pub fn download(
+    url: &str,
+    target: &Path,
+) -> Result<Option<String>, StorageError> {
+    let partial = partial_name(target);
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    let content_type = loop {
+        match fetch(url, &partial) {
+            Ok(content_type) => break content_type,
+            Err(Failure::Fail(e)) => return Err(e),
+            Err(Failure::Retry(_, e)) if attempt >= ATTEMPTS => return Err(e),
+            Err(Failure::Retry(retry_after, e)) => {
+                log::debug!("Attempt {} of {} failed: {:?}", attempt, url, e);
+                let wait = retry_after.unwrap_or(backoff).min(MAX_BACKOFF);
+                println!(
+                    "Download of {} failed, retrying in {} seconds",
+                    url,
+                    wait.as_secs()
+                );
+                std::thread::sleep(wait);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                attempt += 1;
+            }
+        }
+    };
+
+    std::fs::rename(&partial, target)?;
+    Ok(content_type)
+}
+
+/// Remove what was downloaded of the target so far, so the next download
+/// starts from the beginning -- e.g., when the content comes from another
+/// URL, which may not have the same file.
+pub fn discard_partial(target: &Path) -> Result<(), StorageError> {
+    match std::fs::remove_file(partial_name(target)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// The file used while the content is being downloaded.
+fn partial_name(target: &Path) -> PathBuf {
+    let mut name = target.as_os_str().to_owned();
+    name.push(".part");
+    PathBuf::from(name)
+}
+
+/// A single attempt of downloading the URL, continuing the partial file if
+/// there is one.
+fn fetch(url: &str, partial: &Path) -> Result<Option<String>, Failure> {
+    let client = client().map_err(Failure::Fail)?;
+    let offset = std::fs::metadata(partial)
+        .map(|meta| meta.len())
+        .unwrap_or(0);
+    let mut request = client.get(url);
+    if offset > 0 {
+        log::debug!("Resuming {} from byte {}", url, offset);
+        request = request.header(RANGE, format!("bytes={}-", offset));
+    }
+
+    let mut response = request.send().map_err(|e| {
+        if e.is_timeout() {
+            Failure::Retry(None, e.into())
+        } else {
+            Failure::Fail(e.into())
+        }
+    })?;
+
+    let status = response.status();
+    if status == StatusCode::RANGE_NOT_SATISFIABLE {
+        // Whatever we have doesn't match the file anymore; start over.
+        std::fs::remove_file(partial).map_err(|e| Failure::Fail(e.into()))?;
+        return Err(Failure::Retry(
+            Some(Duration::from_secs(0)),
+            response.error_for_status().unwrap_err().into(),
+        ));
+    }
+    if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = retry_after(&response);
+        return Err(Failure::Retry(
+            retry_after,
+            response.error_for_status().unwrap_err().into(),
+        ));
+    }
+    if let Err(e) = response.error_for_status_ref() {
+        return Err(Failure::Fail(e.into()));
+    }
+
+    let mut target = if status == StatusCode::PARTIAL_CONTENT {
+        if range_start(&response) != Some(offset) {
+            // The server sent a different part of the file than the one we
+            // asked for; appending it would corrupt the file, so start over.
+            log::debug!("Unexpected range for {}, restarting", url);
+            std::fs::remove_file(partial)
+                .map_err(|e| Failure::Fail(e.into()))?;
+            return Err(Failure::Retry(
+                Some(Duration::from_secs(0)),
+                StorageError::UnexpectedRange,
+            ));
+        }
+        OpenOptions::new().append(true).open(partial)
+    } else {
+        // The server ignored the range and is sending everything.
+        File::create(partial)
+    }
+    .map_err(|e| Failure::Fail(e.into()))?;
+
+    // Errors while receiving the content (e.g., the connection dropped) are
+    // retried, keeping what was already received.
+    std::io::copy(&mut response, &mut target)
+        .map_err(|e| Failure::Retry(None, e.into()))?;
+    target.flush().map_err(|e| Failure::Fail(e.into()))?;
+    Ok(response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from))
+}
+
+/// The first byte of the content sent in a partial response, from its
+/// `Content-Range` header (`bytes <start>-<end>/<size>`).
+fn range_start(response: &Response) -> Option<u64> {
+    let value = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    value
+        .trim()
+        .strip_prefix("bytes ")?
+        .split('-')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// How long the server asked us to wait, either in seconds or until a date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let when = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    (when.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
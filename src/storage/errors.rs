@@ -0,0 +1,60 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+/// Errors from the storages
+#[derive(Debug)]
+pub enum StorageError {
+    /// Something went wrong reading or writing in the filesystem
+    Io(std::io::Error),
+    /// The attachment couldn't be downloaded
+    Download(reqwest::Error),
+    /// The content couldn't be converted to the storage format
+    Conversion,
+    /// The database refused the content
+    Database(rusqlite::Error),
+    /// The server sent a different part of the attachment than the one asked
+    UnexpectedRange,
+}
+
+impl From<std::io::Error> for StorageError {
+    fn from(e: std::io::Error) -> Self {
+        log::debug!("I/O error: {:?}", e);
+        StorageError::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(e: rusqlite::Error) -> Self {
+        log::debug!("Database error: {:?}", e);
+        StorageError::Database(e)
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(e: serde_json::Error) -> Self {
+        log::debug!("JSON error: {:?}", e);
+        StorageError::Conversion
+    }
+}
+
+impl From<reqwest::Error> for StorageError {
+    fn from(e: reqwest::Error) -> Self {
+        log::debug!("Download error: {:?}", e);
+        StorageError::Download(e)
+    }
+}
@@ -0,0 +1,92 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::io::Write;
+
+use log_derive::logfn;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+
+use crate::config::errors::ConfigError;
+use crate::config::Configurable;
+
+/// How many toots the feed keeps, if not configured.
+pub const DEFAULT_ENTRIES: usize = 50;
+
+/// The format of the feed file.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedFormat {
+    Atom,
+    Rss,
+}
+
+/// Configuration for the feed backend
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FeedConfig {
+    /// The feed file.
+    pub path: String,
+    pub format: FeedFormat,
+    /// How many of the most recently archived toots the feed keeps.
+    #[serde(default = "default_entries")]
+    pub entries: usize,
+}
+
+fn default_entries() -> usize {
+    DEFAULT_ENTRIES
+}
+
+impl Configurable for FeedConfig {
+    #[logfn(Trace)]
+    fn config() -> Result<Self, ConfigError> {
+        print!("Feed file: ");
+        std::io::stdout().flush().expect("Failed to flush stdout!");
+
+        let mut path = String::new();
+        std::io::stdin().read_line(&mut path)?;
+        let fullpath = shellexpand::full(path.trim())?;
+
+        print!("Feed format? [A]tom, [r]ss: ");
+        std::io::stdout().flush().expect("Failed to flush stdout!");
+
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        let format = match answer.trim().to_lowercase().as_ref() {
+            "r" | "rss" => FeedFormat::Rss,
+            _ => FeedFormat::Atom,
+        };
+
+        print!("How many toots in the feed? [{}] ", DEFAULT_ENTRIES);
+        std::io::stdout().flush().expect("Failed to flush stdout!");
+
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        let entries = answer
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|entries| *entries > 0)
+            .unwrap_or(DEFAULT_ENTRIES);
+
+        Ok(Self {
+            path: fullpath.into(),
+            format,
+            entries,
+        })
+    }
+}
@@ -0,0 +1,338 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+use std::path::PathBuf;
+
+use chrono::DateTime;
+use chrono::SecondsFormat;
+use chrono::Utc;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+
+use super::config::FeedConfig;
+use super::config::FeedFormat;
+use crate::storage::data::Data;
+use crate::storage::document::html;
+use crate::storage::document::html::escape;
+use crate::storage::document::Document;
+use crate::storage::errors::StorageError;
+use crate::storage::storage::Storage;
+
+/// A feed (Atom or RSS) with the most recently archived toots.
+///
+/// The toots in the feed are kept in a JSON file next to it
+/// (`<feed>.entries.json`), so each run only adds the new ones; both files
+/// are written once, when the storage is closed, by writing a temporary file
+/// and renaming it, so a feed reader never sees a partial feed.
+pub struct Feed {
+    path: PathBuf,
+    format: FeedFormat,
+    limit: usize,
+    /// The toots in the feed, most recently archived first; only loaded when
+    /// something is saved.
+    entries: RefCell<Option<Vec<Entry>>>,
+}
+
+/// A toot, as it appears in the feed.
+#[derive(Serialize, Deserialize, Debug)]
+struct Entry {
+    /// The address of the toot, used as its unique ID.
+    id: String,
+    title: String,
+    author: String,
+    author_url: String,
+    link: String,
+    published: DateTime<Utc>,
+    /// When the toot was archived.
+    updated: DateTime<Utc>,
+    /// The content, in HTML.
+    content: String,
+    enclosures: Vec<Enclosure>,
+}
+
+/// An attachment of the toot.
+#[derive(Serialize, Deserialize, Debug)]
+struct Enclosure {
+    url: String,
+    mime_type: String,
+}
+
+impl Storage for Feed {
+    fn save(&self, data: &Data) -> Result<(), StorageError> {
+        let document = Document::from(data);
+        let mut content = html::render(&document);
+        for attachment in data.attachments.iter() {
+            if let Some(description) = attachment.description() {
+                content.push_str(&format!("<p>{}</p>", escape(description)));
+            }
+        }
+        let status = &data.status;
+        let entry = Entry {
+            id: status.uri.to_string(),
+            title: match data.title.as_str() {
+                "" => format!("{} (@{})", data.display_name, data.account),
+                title => format!(
+                    "{} (@{}): {}",
+                    data.display_name, data.account, title
+                ),
+            },
+            author: data.display_name.to_string(),
+            author_url: status.account.url.to_string(),
+            link: data.source.to_string(),
+            published: data.created_at,
+            updated: Utc::now(),
+            content,
+            enclosures: data
+                .attachments
+                .iter()
+                .map(|attachment| Enclosure {
+                    url: attachment.url().to_string(),
+                    mime_type: mime_type(
+                        &attachment.filename(),
+                        attachment.media_type(),
+                    ),
+                })
+                .collect(),
+        };
+
+        let mut entries = self.entries.borrow_mut();
+        let entries = match entries.as_mut() {
+            Some(entries) => entries,
+            None => entries.get_or_insert(self.load()?),
+        };
+        entries.retain(|other| other.id != entry.id);
+        entries.insert(0, entry);
+        entries.truncate(self.limit);
+        println!("Added to {}", self.path.to_string_lossy());
+        Ok(())
+    }
+
+    fn close(&self) -> Result<(), StorageError> {
+        let entries = self.entries.borrow();
+        let entries = match entries.as_ref() {
+            Some(entries) => entries,
+            None => return Ok(()),
+        };
+
+        write_atomically(
+            &self.entries_path(),
+            &serde_json::to_string_pretty(entries)?,
+        )?;
+        let feed = match self.format {
+            FeedFormat::Atom => self.atom(entries),
+            FeedFormat::Rss => self.rss(entries),
+        };
+        write_atomically(&self.path, &feed)?;
+        println!("Saved to {}", self.path.to_string_lossy());
+        Ok(())
+    }
+}
+
+impl Feed {
+    pub fn new(config: &FeedConfig) -> Self {
+        Self {
+            path: Path::new(&config.path).to_path_buf(),
+            format: config.format,
+            limit: config.entries,
+            entries: RefCell::new(None),
+        }
+    }
+
+    /// The file with the toots in the feed.
+    fn entries_path(&self) -> PathBuf {
+        let mut name = self.path.as_os_str().to_os_string();
+        name.push(".entries.json");
+        PathBuf::from(name)
+    }
+
+    /// Load the toots already in the feed.
+    fn load(&self) -> Result<Vec<Entry>, StorageError> {
+        match File::open(self.entries_path()) {
+            Ok(fp) => Ok(serde_json::from_reader(fp)?),
+            Err(_) => Ok(Vec::new()),
+        }
+    }
+
+    /// A name for the feed.
+    fn title(&self) -> String {
+        match self.path.file_stem() {
+            Some(stem) => format!("Archived toots: {}", stem.to_string_lossy()),
+            None => "Archived toots".into(),
+        }
+    }
+
+    fn atom(&self, entries: &[Entry]) -> String {
+        let updated = entries
+            .iter()
+            .map(|entry| entry.updated)
+            .max()
+            .unwrap_or_else(Utc::now);
+        let mut feed = format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<feed xmlns=\"http://www.w3.org/2005/Atom\">
+<title>{title}</title>
+<id>urn:downfav:{id}</id>
+<updated>{updated}</updated>
+<generator>downfav</generator>
+",
+            title = escape(&self.title()),
+            id = escape(&self.path.to_string_lossy()),
+            updated = timestamp(&updated)
+        );
+        for entry in entries.iter() {
+            feed.push_str(&format!(
+                "<entry>
+<id>{id}</id>
+<title>{title}</title>
+<author><name>{author}</name><uri>{author_url}</uri></author>
+<link rel=\"alternate\" type=\"text/html\" href=\"{link}\"/>
+<published>{published}</published>
+<updated>{updated}</updated>
+<content type=\"html\">{content}</content>
+",
+                id = escape(&entry.id),
+                title = escape(&entry.title),
+                author = escape(&entry.author),
+                author_url = escape(&entry.author_url),
+                link = escape(&entry.link),
+                published = timestamp(&entry.published),
+                updated = timestamp(&entry.updated),
+                content = escape(&entry.content)
+            ));
+            for enclosure in entry.enclosures.iter() {
+                feed.push_str(&format!(
+                    "<link rel=\"enclosure\" type=\"{}\" href=\"{}\"/>\n",
+                    escape(&enclosure.mime_type),
+                    escape(&enclosure.url)
+                ));
+            }
+            feed.push_str("</entry>\n");
+        }
+        feed.push_str("</feed>\n");
+        feed
+    }
+
+    fn rss(&self, entries: &[Entry]) -> String {
+        let updated = entries
+            .iter()
+            .map(|entry| entry.updated)
+            .max()
+            .unwrap_or_else(Utc::now);
+        let mut feed = format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<rss version=\"2.0\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\">
+<channel>
+<title>{title}</title>
+<link>{link}</link>
+<description>{title}</description>
+<lastBuildDate>{updated}</lastBuildDate>
+<generator>downfav</generator>
+",
+            title = escape(&self.title()),
+            link = escape(
+                &entries
+                    .first()
+                    .map(|entry| entry.link.to_string())
+                    .unwrap_or_default()
+            ),
+            updated = updated.to_rfc2822()
+        );
+        for entry in entries.iter() {
+            feed.push_str(&format!(
+                "<item>
+<guid isPermaLink=\"false\">{id}</guid>
+<title>{title}</title>
+<dc:creator>{author}</dc:creator>
+<link>{link}</link>
+<pubDate>{published}</pubDate>
+<description>{content}</description>
+",
+                id = escape(&entry.id),
+                title = escape(&entry.title),
+                author = escape(&entry.author),
+                link = escape(&entry.link),
+                published = entry.published.to_rfc2822(),
+                content = escape(&entry.content)
+            ));
+            // The size of the attachments is not known, as they are not
+            // downloaded for the feed.
+            for enclosure in entry.enclosures.iter() {
+                feed.push_str(&format!(
+                    "<enclosure url=\"{}\" length=\"0\" type=\"{}\"/>\n",
+                    escape(&enclosure.url),
+                    escape(&enclosure.mime_type)
+                ));
+            }
+            feed.push_str("</item>\n");
+        }
+        feed.push_str("</channel>\n</rss>\n");
+        feed
+    }
+}
+
+/// The MIME type of an attachment, guessed from its name or, if the name has
+/// no known extension, from its media type.
+fn mime_type(filename: &str, media_type: &str) -> String {
+    let extension = Path::new(filename)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    let mime_type = match extension.as_deref() {
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("mp4") | Some("m4v") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("mov") => "video/quicktime",
+        Some("mp3") => "audio/mpeg",
+        Some("ogg") | Some("oga") => "audio/ogg",
+        Some("m4a") => "audio/mp4",
+        _ => match media_type {
+            "image" => "image/*",
+            "video" | "gifv" => "video/*",
+            _ => "application/octet-stream",
+        },
+    };
+    mime_type.into()
+}
+
+/// A date, as used by Atom.
+fn timestamp(date: &DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Write the file by writing a temporary file next to it and renaming it
+/// over the old one.
+fn write_atomically(path: &Path, content: &str) -> Result<(), StorageError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".tmp");
+    let temporary = PathBuf::from(name);
+    let mut fp = File::create(&temporary)?;
+    fp.write_all(content.as_bytes())?;
+    fp.sync_all()?;
+    std::fs::rename(&temporary, path)?;
+    Ok(())
+}
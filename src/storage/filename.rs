@@ -0,0 +1,258 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Safe filenames for attachments.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+/// The longest name (in bytes, without the extension) we produce; most
+/// filesystems accept 255 bytes, but we leave room for prefixes and
+/// numbering.
+const MAX_STEM: usize = 100;
+
+/// Names that can't be used as files on Windows.
+const RESERVED: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6",
+    "com7", "com8", "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6",
+    "lpt7", "lpt8", "lpt9",
+];
+
+/// Turn any string into a name that can be used as a file in every
+/// filesystem (and as a link in Markdown and Org files): no path separators,
+/// no special characters, and not too long.
+pub fn sanitize(name: &str) -> String {
+    let clean: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "-_.+~@=,".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let clean = clean.trim_matches('.');
+
+    let (stem, extension) = split(clean);
+    let mut stem = truncate(stem, MAX_STEM).to_string();
+    if stem.is_empty() {
+        stem = "attachment".into();
+    }
+    if RESERVED.contains(&stem.to_lowercase().as_str()) {
+        stem.insert(0, '_');
+    }
+    join(&stem, extension)
+}
+
+/// Decode the percent-encoded characters in a URL segment (e.g., `%20`).
+pub fn decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let hex = bytes
+            .get(pos + 1..pos + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[pos], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                pos += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                pos += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Add the extension to the name, if it doesn't have one.
+pub fn with_extension(name: &str, extension: Option<&str>) -> String {
+    match (split(name), extension) {
+        ((_, None), Some(extension)) if !extension.is_empty() => {
+            format!("{}.{}", name, sanitize(extension))
+        }
+        _ => name.to_string(),
+    }
+}
+
+/// The extension for a file of the given content type (e.g., `image/jpeg`),
+/// if it is a known one.
+pub fn extension_for(content_type: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next()?.trim().to_lowercase();
+    let extension = match mime.as_str() {
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/avif" => "avif",
+        "image/heic" => "heic",
+        "image/svg+xml" => "svg",
+        "video/mp4" => "mp4",
+        "video/webm" => "webm",
+        "video/quicktime" => "mov",
+        "audio/mpeg" => "mp3",
+        "audio/ogg" => "ogg",
+        "audio/wav" | "audio/x-wav" => "wav",
+        "audio/flac" => "flac",
+        "audio/mp4" => "m4a",
+        _ => return None,
+    };
+    Some(extension)
+}
+
+/// Make all names unique, numbering the ones that clash with a previous one
+/// (`image.png`, `image-2.png`, `image-3.png`...). Names are compared
+/// ignoring case, as some filesystems do.
+pub fn disambiguate(names: Vec<String>) -> Vec<String> {
+    let mut used = HashSet::new();
+    names
+        .into_iter()
+        .map(|name| {
+            let (stem, extension) = split(&name);
+            let mut candidate = name.to_string();
+            let mut counter = 1;
+            while !used.insert(candidate.to_lowercase()) {
+                counter += 1;
+                candidate = join(&format!("{}-{}", stem, counter), extension);
+            }
+            candidate
+        })
+        .collect()
+}
+
+/// Split the name in stem and extension.
+fn split(name: &str) -> (&str, Option<&str>) {
+    let path = Path::new(name);
+    match (
+        path.file_stem().and_then(|stem| stem.to_str()),
+        path.extension().and_then(|extension| extension.to_str()),
+    ) {
+        (Some(stem), Some(extension)) => (stem, Some(extension)),
+        _ => (name, None),
+    }
+}
+
+fn join(stem: &str, extension: Option<&str>) -> String {
+    match extension {
+        Some(extension) => format!("{}.{}", stem, truncate(extension, 16)),
+        None => stem.to_string(),
+    }
+}
+
+/// Cut the string to, at most, `limit` bytes, without breaking characters.
+fn truncate(text: &str, limit: usize) -> &str {
+    if text.len() <= limit {
+        return text;
+    }
+    let mut end = limit;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_separators_are_replaced() {
+        for name in &["../../etc/passwd", "..\\..\\windows\\system.ini", "a/b"]
+        {
+            let clean = sanitize(name);
+            assert!(!clean.contains('/'), "{}", clean);
+            assert!(!clean.contains('\\'), "{}", clean);
+            assert!(!clean.starts_with('.'), "{}", clean);
+        }
+        assert_eq!(sanitize("../../etc/passwd"), "_.._etc_passwd");
+    }
+
+    #[test]
+    fn special_characters_are_replaced() {
+        assert_eq!(sanitize("a b:c*d?.png"), "a_b_c_d_.png");
+        assert_eq!(sanitize("café.jpg"), "café.jpg");
+    }
+
+    #[test]
+    fn empty_and_reserved_names() {
+        assert_eq!(sanitize(""), "attachment");
+        assert_eq!(sanitize("..."), "attachment");
+        assert_eq!(sanitize("con.txt"), "_con.txt");
+        assert_eq!(sanitize("NUL"), "_NUL");
+    }
+
+    #[test]
+    fn long_names_are_cut() {
+        let long = format!("{}.png", "a".repeat(300));
+        assert_eq!(sanitize(&long), format!("{}.png", "a".repeat(MAX_STEM)));
+
+        let extension = format!("image.{}", "x".repeat(40));
+        assert_eq!(sanitize(&extension), format!("image.{}", "x".repeat(16)));
+
+        // Multi-byte characters are never split.
+        let accents = "é".repeat(MAX_STEM);
+        let clean = sanitize(&accents);
+        assert!(clean.len() <= MAX_STEM);
+        assert!(clean.chars().all(|c| c == 'é'));
+    }
+
+    #[test]
+    fn decode_percent_encoding() {
+        assert_eq!(decode("my%20picture.png"), "my picture.png");
+        assert_eq!(decode("100%.png"), "100%.png");
+        assert_eq!(decode("%zz%4"), "%zz%4");
+        assert_eq!(sanitize(&decode("a%2Fb.png")), "a_b.png");
+    }
+
+    #[test]
+    fn extension_from_content_type() {
+        assert_eq!(extension_for("image/jpeg"), Some("jpg"));
+        assert_eq!(extension_for("Video/MP4; codecs=avc1"), Some("mp4"));
+        assert_eq!(extension_for("application/octet-stream"), None);
+
+        assert_eq!(with_extension("image", Some("png")), "image.png");
+        assert_eq!(with_extension("image.jpg", Some("png")), "image.jpg");
+        assert_eq!(with_extension("image", None), "image");
+    }
+
+    #[test]
+    fn clashing_names_are_numbered() {
+        let names = vec![
+            "image.png".to_string(),
+            "IMAGE.png".to_string(),
+            "image.png".to_string(),
+            "other".to_string(),
+            "other".to_string(),
+        ];
+        assert_eq!(
+            disambiguate(names),
+            vec![
+                "image.png",
+                "IMAGE-2.png",
+                "image-3.png",
+                "other",
+                "other-2"
+            ]
+        );
+    }
+}
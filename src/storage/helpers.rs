@@ -16,19 +16,92 @@
    along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 use crate::storage::data::Data;
+use crate::storage::document::markdown;
+use crate::storage::document::markdown::escape;
+use crate::storage::document::Document;
+use crate::storage::store::StoredAttachment;
 
-// XXX Easily could be tied to the HTML-to-Org parser.
-pub fn make_markdown(status: &Data) -> String {
-    let base_content = html2md::parse_html(&status.text);
-    let title = &status.title;
+/// Quote a string for the front matter; JSON strings are also valid YAML.
+fn quote(text: &str) -> String {
+    serde_json::to_string(text).unwrap_or_else(|_| format!("{:?}", text))
+}
+
+/// A list of strings for the front matter.
+fn quote_list(items: &[String]) -> String {
+    let quoted: Vec<String> = items.iter().map(|item| quote(item)).collect();
+    format!("[{}]", quoted.join(", "))
+}
 
+/// YAML front matter with the toot metadata.
+fn make_front_matter(status: &Data) -> String {
+    let mut result = String::from("---\n");
+    result.push_str(&format!("id: {}\n", quote(&status.id)));
+    result.push_str(&format!("account: {}\n", quote(&status.account)));
+    result.push_str(&format!("origin: {}\n", quote(status.origin.name())));
+    result.push_str(&format!("author: {}\n", quote(&status.display_name)));
+    result
+        .push_str(&format!("created_at: {}\n", status.created_at.to_rfc3339()));
+    if let Some(language) = &status.language {
+        result.push_str(&format!("language: {}\n", quote(language)));
+    }
+    result.push_str(&format!("visibility: {}\n", quote(&status.visibility)));
+    result.push_str(&format!("sensitive: {}\n", status.sensitive));
+    if !status.title.is_empty() {
+        result
+            .push_str(&format!("content_warning: {}\n", quote(&status.title)));
+    }
+    if let Some(in_reply_to) = &status.in_reply_to {
+        result.push_str(&format!("in_reply_to: {}\n", quote(in_reply_to)));
+    }
+    result.push_str(&format!("tags: {}\n", quote_list(&status.tags)));
+    result.push_str(&format!("mentions: {}\n", quote_list(&status.mentions)));
+    if !status.source.is_empty() {
+        result.push_str(&format!("source: {}\n", quote(&status.source)));
+    }
+    result.push_str("---\n\n");
+    result
+}
+
+/// Links to the attachments, which are saved alongside the toot, with their
+/// alt text as captions.
+fn make_attachments(files: &[StoredAttachment]) -> String {
     let mut result = String::new();
-    if title.len() > 0 {
+    for file in files.iter() {
+        let attachment = file.attachment;
+        let description = attachment.description().map(escape);
+        let filename = &file.name;
+        result.push_str("\n\n");
+        match attachment.media_type() {
+            "image" => result.push_str(&format!(
+                "![{}]({})",
+                description.as_deref().unwrap_or(""),
+                filename
+            )),
+            _ => result.push_str(&format!(
+                "[{}]({})",
+                escape(filename),
+                filename
+            )),
+        }
+        if let Some(description) = description {
+            result.push_str(&format!("\n\n_{}_", description));
+        }
+    }
+    result
+}
+
+pub fn make_markdown(status: &Data, files: &[StoredAttachment]) -> String {
+    let base_content = markdown::render(&Document::from(status));
+    let title = &status.title;
+
+    let mut result = make_front_matter(status);
+    if !title.is_empty() {
         result.push_str(title);
         result.push_str("\n\n");
     }
 
     result.push_str(&base_content);
+    result.push_str(&make_attachments(files));
 
     if !status.source.is_empty() {
         result.push_str("\n\n");
@@ -37,3 +110,51 @@ pub fn make_markdown(status: &Data) -> String {
 
     result
 }
+
+/// One toot of a conversation, in Markdown; attachments are linked to their
+/// URLs, as they are not downloaded.
+fn make_conversation_entry(status: &Data, favourite: bool) -> String {
+    let mut result = format!(
+        "## {} (@{}), {}{}\n\n",
+        escape(&status.display_name),
+        escape(&status.account),
+        status.created_at.format("%Y-%m-%d %H:%M"),
+        if favourite { " -- favourite" } else { "" }
+    );
+    if !status.title.is_empty() {
+        result.push_str(&format!("**{}**\n\n", escape(&status.title)));
+    }
+    result.push_str(markdown::render(&Document::from(status)).trim_end());
+    for attachment in status.attachments.iter() {
+        let name = attachment
+            .description()
+            .map(escape)
+            .unwrap_or_else(|| escape(&attachment.filename()));
+        result.push_str(&format!("\n\n[{}]({})", name, attachment.url()));
+    }
+    if !status.source.is_empty() {
+        result.push_str(&format!("\n\n<{}>", status.source));
+    }
+    result.push_str("\n\n");
+    result
+}
+
+/// The conversation around a favourite: the toots it replies to, the
+/// favourite itself and, if requested, the replies to it.
+pub fn make_conversation(status: &Data, with_descendants: bool) -> String {
+    let mut entries: Vec<String> = status
+        .ancestors
+        .iter()
+        .map(|ancestor| make_conversation_entry(ancestor, false))
+        .collect();
+    entries.push(make_conversation_entry(status, true));
+    if with_descendants {
+        entries.extend(
+            status
+                .descendants
+                .iter()
+                .map(|descendant| make_conversation_entry(descendant, false)),
+        );
+    }
+    format!("# Conversation\n\n{}", entries.join("---\n\n"))
+}
@@ -0,0 +1,528 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+
+use super::config::HtmlConfig;
+use crate::storage::data::Data;
+use crate::storage::document::html;
+use crate::storage::document::html::escape;
+use crate::storage::document::Document;
+use crate::storage::errors::StorageError;
+use crate::storage::filename::sanitize;
+use crate::storage::storage::Storage;
+use crate::storage::store::AttachmentStore;
+use crate::storage::store::StoredAttachment;
+
+/// The longest summary of a toot in the index pages, in characters.
+const SUMMARY_LENGTH: usize = 140;
+
+/// Style for all pages; there is no script at all.
+const STYLE: &str = "body { max-width: 50em; margin: 0 auto; padding: 1em; \
+    font-family: sans-serif; line-height: 1.4; }
+nav a { margin-right: 1em; }
+article header { margin-bottom: 1em; }
+.account { color: #666; }
+img, video { max-width: 100%; height: auto; }
+img.emoji { height: 1.2em; vertical-align: middle; }
+figure { margin: 1em 0; }
+figcaption { color: #666; font-size: 0.9em; }
+footer { margin-top: 1em; color: #666; }
+ul.toots li { margin-bottom: 0.5em; }
+";
+
+/// A static site, with one page per toot and index pages by date, author
+/// and hashtag.
+///
+/// Every toot in the site is listed in `entries.jsonl`, so when new toots
+/// come in, only their pages are built while saving; the index pages they
+/// appear in are built once, when the storage is closed.
+pub struct Html {
+    path: PathBuf,
+    store: Rc<AttachmentStore>,
+    /// The toots in the site; loaded on the first save.
+    entries: RefCell<Option<Entries>>,
+}
+
+/// The toots in the site, and what changed in this run.
+#[derive(Default)]
+struct Entries {
+    /// The toots, by account and ID.
+    list: BTreeMap<(String, String), Entry>,
+    /// The months, authors and tags of the toots saved in this run (before
+    /// and after the change), which need their pages built again.
+    months: BTreeSet<String>,
+    authors: BTreeSet<String>,
+    tags: BTreeSet<String>,
+    /// Whether `entries.jsonl` has toots more than once, from changes.
+    repeated: bool,
+}
+
+/// What the index pages need to know about a toot.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Entry {
+    account: String,
+    id: String,
+    author: String,
+    /// When the toot was posted, in RFC 3339.
+    created_at: String,
+    tags: Vec<String>,
+    /// The content warning or, if there is none, the start of the text.
+    summary: String,
+}
+
+impl Storage for Html {
+    fn save(&self, data: &Data) -> Result<(), StorageError> {
+        let dir = self.toot_dir(data);
+        std::fs::create_dir_all(&dir)?;
+        let files = self.store.files(&data.attachments)?;
+        for file in files.iter() {
+            file.link(&dir.join(&file.name))?;
+        }
+
+        let document = Document::from(data);
+        let entry = Entry {
+            account: data.account.to_string(),
+            id: data.id.to_string(),
+            author: data.display_name.to_string(),
+            created_at: data.created_at.to_rfc3339(),
+            tags: data.tags.iter().map(|tag| tag.to_lowercase()).collect(),
+            summary: summary(data, &document),
+        };
+        write(&dir.join("index.html"), &toot_page(data, &document, &files))?;
+        self.add_entry(entry)?;
+        println!("Saved to {}", dir.to_string_lossy());
+        Ok(())
+    }
+
+    fn close(&self) -> Result<(), StorageError> {
+        let entries = self.entries.borrow();
+        match entries.as_ref() {
+            Some(entries) => self.build_indexes(entries),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Html {
+    pub fn new(config: &HtmlConfig, store: Rc<AttachmentStore>) -> Self {
+        Self {
+            path: Path::new(&config.path).to_path_buf(),
+            store,
+            entries: RefCell::new(None),
+        }
+    }
+
+    /// The directory with the page (and attachments) of a toot.
+    fn toot_dir(&self, data: &Data) -> PathBuf {
+        self.path
+            .join("toots")
+            .join(sanitize(&data.account))
+            .join(sanitize(&data.id))
+    }
+
+    /// The list of toots in the site.
+    fn list_path(&self) -> PathBuf {
+        self.path.join("entries.jsonl")
+    }
+
+    /// Load the toots already in the site; if a toot was changed, the last
+    /// version of it is the one kept.
+    fn load(&self) -> Result<Entries, StorageError> {
+        let mut entries = Entries::default();
+        if let Ok(fp) = File::open(self.list_path()) {
+            for line in BufReader::new(fp).lines() {
+                match serde_json::from_str::<Entry>(&line?) {
+                    Ok(entry) => {
+                        let key =
+                            (entry.account.to_string(), entry.id.to_string());
+                        if entries.list.insert(key, entry).is_some() {
+                            entries.repeated = true;
+                        }
+                    }
+                    Err(e) => log::debug!("Invalid entry: {:?}", e),
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Add (or replace) the toot in the list of toots in the site; the list
+    /// file only changes if the toot is new or different.
+    fn add_entry(&self, entry: Entry) -> Result<(), StorageError> {
+        let mut entries = self.entries.borrow_mut();
+        let entries = match entries.as_mut() {
+            Some(entries) => entries,
+            None => entries.get_or_insert(self.load()?),
+        };
+
+        let key = (entry.account.to_string(), entry.id.to_string());
+        let previous = entries.list.get(&key);
+        if previous != Some(&entry) {
+            let mut fp = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.list_path())?;
+            writeln!(fp, "{}", serde_json::to_string(&entry)?)?;
+            entries.repeated |= previous.is_some();
+        }
+        for changed in previous.into_iter().chain(Some(&entry)) {
+            entries.months.insert(changed.created_at[..7].into());
+            entries.authors.insert(changed.account.to_string());
+            entries.tags.extend(changed.tags.iter().cloned());
+        }
+        entries.list.insert(key, entry);
+        Ok(())
+    }
+
+    /// Build the main index pages, and the pages of the months, authors and
+    /// hashtags of the toots saved in this run.
+    fn build_indexes(&self, entries: &Entries) -> Result<(), StorageError> {
+        let list: Vec<&Entry> = entries.list.values().collect();
+        if entries.repeated {
+            let mut content = String::new();
+            for entry in list.iter() {
+                content.push_str(&serde_json::to_string(entry)?);
+                content.push('\n');
+            }
+            // The list is the only record of the toots in the site, so it
+            // is replaced only once completely written.
+            let temporary = self.path.join("entries.jsonl.new");
+            write(&temporary, &content)?;
+            std::fs::rename(&temporary, self.list_path())?;
+        }
+        write(&self.path.join("style.css"), STYLE)?;
+
+        write(
+            &self.path.join("index.html"),
+            &group_page(
+                "Archive",
+                "dates",
+                &groups(&list, |entry| vec![entry.created_at[..7].into()]),
+            ),
+        )?;
+        for month in entries.months.iter() {
+            write(
+                &self.path.join("dates").join(format!("{}.html", month)),
+                &list_page(
+                    month,
+                    &list
+                        .iter()
+                        .filter(|entry| entry.created_at.starts_with(month))
+                        .copied()
+                        .collect::<Vec<&Entry>>(),
+                ),
+            )?;
+        }
+
+        write(
+            &self.path.join("authors.html"),
+            &group_page(
+                "Authors",
+                "authors",
+                &groups(&list, |entry| vec![entry.account.to_string()]),
+            ),
+        )?;
+        for account in entries.authors.iter() {
+            let toots = list
+                .iter()
+                .filter(|entry| &entry.account == account)
+                .copied()
+                .collect::<Vec<&Entry>>();
+            let author = toots
+                .iter()
+                .max_by(|a, b| a.created_at.cmp(&b.created_at))
+                .map(|entry| entry.author.as_str())
+                .unwrap_or_default();
+            write(
+                &self
+                    .path
+                    .join("authors")
+                    .join(format!("{}.html", sanitize(account))),
+                &list_page(&format!("{} (@{})", author, account), &toots),
+            )?;
+        }
+
+        write(
+            &self.path.join("tags.html"),
+            &group_page(
+                "Tags",
+                "tags",
+                &groups(&list, |entry| entry.tags.clone()),
+            ),
+        )?;
+        for tag in entries.tags.iter() {
+            write(
+                &self
+                    .path
+                    .join("tags")
+                    .join(format!("{}.html", sanitize(tag))),
+                &list_page(
+                    &format!("#{}", tag),
+                    &list
+                        .iter()
+                        .filter(|entry| entry.tags.contains(tag))
+                        .copied()
+                        .collect::<Vec<&Entry>>(),
+                ),
+            )?;
+        }
+        println!("Saved to {}", self.path.to_string_lossy());
+        Ok(())
+    }
+}
+
+/// The page of a single toot, with its attachments.
+fn toot_page(
+    data: &Data,
+    document: &Document,
+    files: &[StoredAttachment],
+) -> String {
+    let mut content = html::render(document);
+    for file in files.iter() {
+        content.push('\n');
+        content.push_str(&attachment(file));
+    }
+    if !data.title.is_empty() {
+        content = format!(
+            "<details>\n<summary>{}</summary>\n{}\n</details>",
+            escape(&data.title),
+            content
+        );
+    }
+
+    let mut footer = Vec::new();
+    for tag in data.tags.iter() {
+        footer.push(format!(
+            "<a href=\"../../../tags/{}.html\">#{}</a>",
+            escape(&sanitize(&tag.to_lowercase())),
+            escape(tag)
+        ));
+    }
+    if html::is_safe(&data.source) {
+        footer
+            .push(format!("<a href=\"{}\">Original</a>", escape(&data.source)));
+    }
+    footer.push(format!("Archived as {}", data.origin.name()));
+
+    let body = format!(
+        "<article>
+<header>
+<a href=\"../../../authors/{author_page}.html\">{author}</a>
+<span class=\"account\">@{account}</span><br>
+<time datetime=\"{datetime}\">{date}</time>
+</header>
+{content}
+<footer>{footer}</footer>
+</article>",
+        author_page = escape(&sanitize(&data.account)),
+        author = escape(&data.display_name),
+        account = escape(&data.account),
+        datetime = data.created_at.to_rfc3339(),
+        date = data.created_at.format("%Y-%m-%d %H:%M"),
+        content = content,
+        footer = footer.join(" · ")
+    );
+    page(
+        &format!("{}: {}", data.display_name, summary(data, document)),
+        data.language.as_deref(),
+        3,
+        &body,
+    )
+}
+
+/// An attachment, embedded in the page when the browser can show it.
+fn attachment(file: &StoredAttachment) -> String {
+    let name = escape(&file.name);
+    let description = file.attachment.description().map(escape);
+    let alt = description.as_deref().unwrap_or_default();
+    let media = match file.attachment.media_type() {
+        "image" => format!(
+            "<a href=\"{name}\"><img src=\"{name}\" alt=\"{alt}\"></a>",
+            name = name,
+            alt = alt
+        ),
+        "video" => format!(
+            "<video src=\"{name}\" controls preload=\"metadata\" title=\"{alt}\">\
+             <a href=\"{name}\">{name}</a></video>",
+            name = name,
+            alt = alt
+        ),
+        "gifv" => format!(
+            "<video src=\"{name}\" autoplay loop muted playsinline title=\"{alt}\">\
+             <a href=\"{name}\">{name}</a></video>",
+            name = name,
+            alt = alt
+        ),
+        _ => format!("<a href=\"{name}\">{name}</a>", name = name),
+    };
+    match description {
+        Some(description) => format!(
+            "<figure>\n{}\n<figcaption>{}</figcaption>\n</figure>",
+            media, description
+        ),
+        None => format!("<figure>\n{}\n</figure>", media),
+    }
+}
+
+/// Index page listing groups of toots (months, authors or tags), each
+/// linking to its own page in the directory, with the number of toots in it.
+fn group_page(
+    title: &str,
+    directory: &str,
+    groups: &BTreeMap<String, usize>,
+) -> String {
+    let mut names: Vec<(&String, &usize)> = groups.iter().collect();
+    // Months are listed from the most recent; everything else, by name.
+    if directory == "dates" {
+        names.reverse();
+    }
+    let items = names
+        .iter()
+        .map(|(name, count)| {
+            format!(
+                "<li><a href=\"{}/{}.html\">{}</a> ({})</li>",
+                directory,
+                escape(&sanitize(name)),
+                escape(name),
+                count
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    page(
+        title,
+        None,
+        0,
+        &format!("<h1>{}</h1>\n<ul>\n{}\n</ul>", escape(title), items),
+    )
+}
+
+/// Index page listing toots, from the most recent.
+fn list_page(title: &str, entries: &[&Entry]) -> String {
+    let mut entries = entries.to_vec();
+    entries.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    let items = entries
+        .iter()
+        .map(|entry| {
+            format!(
+                "<li><a href=\"../toots/{}/{}/index.html\">{}</a> \
+                 {} <span class=\"account\">@{}</span><br>{}</li>",
+                escape(&sanitize(&entry.account)),
+                escape(&sanitize(&entry.id)),
+                escape(&entry.created_at[..10]),
+                escape(&entry.author),
+                escape(&entry.account),
+                escape(&entry.summary)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    page(
+        title,
+        None,
+        1,
+        &format!(
+            "<h1>{}</h1>\n<ul class=\"toots\">\n{}\n</ul>",
+            escape(title),
+            items
+        ),
+    )
+}
+
+/// A complete page, `depth` directories below the root of the site.
+fn page(
+    title: &str,
+    language: Option<&str>,
+    depth: usize,
+    body: &str,
+) -> String {
+    let root = "../".repeat(depth);
+    format!(
+        "<!DOCTYPE html>
+<html lang=\"{language}\">
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<title>{title}</title>
+<link rel=\"stylesheet\" href=\"{root}style.css\">
+</head>
+<body>
+<nav>
+<a href=\"{root}index.html\">Dates</a>
+<a href=\"{root}authors.html\">Authors</a>
+<a href=\"{root}tags.html\">Tags</a>
+</nav>
+{body}
+</body>
+</html>
+",
+        language = escape(language.unwrap_or("en")),
+        title = escape(title),
+        root = root,
+        body = body
+    )
+}
+
+/// How many toots are in each group.
+fn groups(
+    entries: &[&Entry],
+    group: impl Fn(&Entry) -> Vec<String>,
+) -> BTreeMap<String, usize> {
+    let mut groups = BTreeMap::new();
+    for entry in entries.iter() {
+        for name in group(entry) {
+            *groups.entry(name).or_insert(0) += 1;
+        }
+    }
+    groups
+}
+
+/// The content warning of the toot or, if there is none, the start of its
+/// text.
+fn summary(data: &Data, document: &Document) -> String {
+    if !data.title.is_empty() {
+        return data.title.to_string();
+    }
+    let text = html::text(document);
+    match text.char_indices().nth(SUMMARY_LENGTH) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text,
+    }
+}
+
+/// Write the file, creating its directory if needed.
+fn write(path: &Path, content: &str) -> Result<(), StorageError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    File::create(path)?.write_all(content.as_bytes())?;
+    Ok(())
+}
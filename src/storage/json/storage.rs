@@ -0,0 +1,95 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::fs::File;
+use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use log_derive::logfn;
+use serde_json::json;
+use serde_json::Value;
+
+use super::config::JsonConfig;
+use crate::storage::data::Data;
+use crate::storage::errors::StorageError;
+use crate::storage::storage::Storage;
+use crate::storage::store::AttachmentStore;
+
+/// Store the full status, as JSON, one file per favourite.
+pub struct Json {
+    path: String,
+    store: Rc<AttachmentStore>,
+}
+
+impl Storage for Json {
+    fn save(&self, data: &Data) -> Result<(), StorageError> {
+        std::fs::create_dir_all(self.account_dir(data))?;
+        let attachments = self.save_attachments(data)?;
+        let document = json!({
+            "origin": data.origin.name(),
+            "status": data.json,
+            "attachments": attachments,
+        });
+
+        let filename = self.account_dir(data).join(format!("{}.json", data.id));
+        let fp = File::create(&filename)?;
+        serde_json::to_writer_pretty(fp, &document)?;
+        println!("Saved to {}", filename.to_string_lossy());
+        Ok(())
+    }
+}
+
+impl Json {
+    pub fn new(config: &JsonConfig, store: Rc<AttachmentStore>) -> Self {
+        Self {
+            path: config.path.to_string(),
+            store,
+        }
+    }
+
+    /// The directory with all the favourites of the toot author.
+    #[logfn(Trace)]
+    fn account_dir(&self, data: &Data) -> PathBuf {
+        Path::new(&self.path).join(&data.account)
+    }
+
+    /// Download the attachments, returning where each one was saved.
+    fn save_attachments(
+        &self,
+        data: &Data,
+    ) -> Result<Vec<Value>, StorageError> {
+        let mut saved = Vec::new();
+        if data.attachments.is_empty() {
+            return Ok(saved);
+        }
+
+        let dir = self.account_dir(data).join(&data.id);
+        std::fs::create_dir_all(&dir)?;
+        for file in self.store.files(&data.attachments)? {
+            let filename = dir.join(&file.name);
+            file.link(&filename)?;
+            saved.push(json!({
+                "url": file.attachment.url(),
+                "path": filename.to_string_lossy(),
+                "sha256": file.hash(),
+            }));
+        }
+        Ok(saved)
+    }
+}
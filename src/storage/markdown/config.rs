@@ -24,12 +24,16 @@ use serde_derive::Serialize;
 
 use crate::config::errors::ConfigError;
 use crate::config::Configurable;
+use crate::storage::thread::ThreadMode;
 
 /// Configuration for the Markdown backend
 #[derive(Serialize, Deserialize, Debug)]
 pub struct MarkdownConfig {
     /// Path where files will be stored.
     pub path: String,
+    /// How much of the conversation of each favourite is saved.
+    #[serde(default)]
+    pub thread: ThreadMode,
 }
 
 impl Configurable for MarkdownConfig {
@@ -41,8 +45,10 @@ impl Configurable for MarkdownConfig {
         let mut path = String::new();
         std::io::stdin().read_line(&mut path)?;
         let fullpath = shellexpand::full(path.trim())?;
+        let thread = ThreadMode::ask()?;
         Ok(Self {
             path: fullpath.into(),
+            thread,
         })
     }
 }
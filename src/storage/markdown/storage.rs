@@ -20,31 +20,47 @@ use std::fs::File;
 use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
+use std::rc::Rc;
 
 use log_derive::logfn;
 
 use super::config::MarkdownConfig;
 use crate::storage::data::Data;
+use crate::storage::errors::StorageError;
+use crate::storage::helpers::make_conversation;
 use crate::storage::helpers::make_markdown;
 use crate::storage::storage::Storage;
+use crate::storage::store::AttachmentStore;
+use crate::storage::store::StoredAttachment;
+use crate::storage::thread::ThreadMode;
 
 pub struct Markdown {
     path: String,
+    thread: ThreadMode,
+    store: Rc<AttachmentStore>,
 }
 
 impl Storage for Markdown {
-    fn save(&self, data: &Data) {
-        self.create_dirs(data);
-        self.save_content(data);
-        self.save_attachments(data);
+    fn thread(&self) -> ThreadMode {
+        self.thread
+    }
+
+    fn save(&self, data: &Data) -> Result<(), StorageError> {
+        self.create_dirs(data)?;
+        let files = self.save_attachments(data)?;
+        self.save_content(data, &files)?;
+        self.save_conversation(data)?;
         println!("Saved to {}", self.dir(data).to_string_lossy());
+        Ok(())
     }
 }
 
 impl Markdown {
-    pub fn new(config: &MarkdownConfig) -> Self {
+    pub fn new(config: &MarkdownConfig, store: Rc<AttachmentStore>) -> Self {
         Self {
             path: config.path.to_string(),
+            thread: config.thread,
+            store,
         }
     }
 
@@ -55,25 +71,48 @@ impl Markdown {
     }
 
     /// Make sure the path structure exists for saving the data.
-    fn create_dirs(&self, data: &Data) {
-        std::fs::create_dir_all(self.dir(data))
-            .expect("Failed to create storage directory");
+    fn create_dirs(&self, data: &Data) -> Result<(), StorageError> {
+        std::fs::create_dir_all(self.dir(data))?;
+        Ok(())
     }
 
     /// Save the content in the directory.
-    fn save_content(&self, data: &Data) {
+    fn save_content(
+        &self,
+        data: &Data,
+        files: &[StoredAttachment],
+    ) -> Result<(), StorageError> {
         let filename = self.dir(data).join("toot.md");
-        let mut fp = File::create(filename).expect("Failed to create file");
-        fp.write_all(make_markdown(data).as_bytes())
-            .expect("Failed to save content");
+        let mut fp = File::create(filename)?;
+        fp.write_all(make_markdown(data, files).as_bytes())?;
+        Ok(())
+    }
+
+    /// Save the conversation around the toot, if requested and there is one.
+    fn save_conversation(&self, data: &Data) -> Result<(), StorageError> {
+        let with_descendants = self.thread.with_descendants();
+        if self.thread == ThreadMode::Off
+            || (data.ancestors.is_empty()
+                && (!with_descendants || data.descendants.is_empty()))
+        {
+            return Ok(());
+        }
+
+        let filename = self.dir(data).join("conversation.md");
+        let mut fp = File::create(filename)?;
+        fp.write_all(make_conversation(data, with_descendants).as_bytes())?;
+        Ok(())
     }
 
     /// Save the attachments.
-    fn save_attachments(&self, data: &Data) {
-        data.attachments.iter().for_each(|attachment| {
-            let filename = self.dir(data).join(attachment.filename());
-            let mut fp = File::create(filename).expect("Failed to create file");
-            attachment.download().copy_to(&mut fp).unwrap();
-        })
+    fn save_attachments<'a>(
+        &self,
+        data: &'a Data,
+    ) -> Result<Vec<StoredAttachment<'a>>, StorageError> {
+        let files = self.store.files(&data.attachments)?;
+        for file in files.iter() {
+            file.link(&self.dir(data).join(&file.name))?;
+        }
+        Ok(files)
     }
 }
@@ -18,7 +18,18 @@
 
 pub mod attachment;
 pub mod data;
+pub mod document;
+pub mod download;
+pub mod errors;
+pub mod feed;
+pub mod filename;
 pub mod helpers;
+pub mod html;
+pub mod json;
 pub mod markdown;
 pub mod org;
+pub mod pool;
+pub mod sqlite;
 pub mod storage;
+pub mod store;
+pub mod thread;
@@ -24,11 +24,15 @@ use serde_derive::Serialize;
 
 use crate::config::errors::ConfigError;
 use crate::config::Configurable;
+use crate::storage::thread::ThreadMode;
 
 /// Configuration for the Org backend
 #[derive(Serialize, Deserialize, Debug)]
 pub struct OrgConfig {
     pub path: String,
+    /// How much of the conversation of each favourite is saved.
+    #[serde(default)]
+    pub thread: ThreadMode,
 }
 
 impl Configurable for OrgConfig {
@@ -41,8 +45,10 @@ impl Configurable for OrgConfig {
         std::io::stdin().read_line(&mut path)?;
         let fullpath = shellexpand::full(path.trim())?;
         log::debug!("Full path: {:?}", fullpath);
+        let thread = ThreadMode::ask()?;
         Ok(Self {
             path: fullpath.into(),
+            thread,
         })
     }
 }
@@ -16,25 +16,25 @@
  along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use std::borrow::Borrow;
-use std::default::Default;
 use std::fs::File;
 use std::fs::OpenOptions;
 use std::io::prelude::*;
 use std::path::Path;
 use std::path::PathBuf;
+use std::rc::Rc;
 
 use chrono::prelude::*;
-use html5ever::parse_document;
-use html5ever::tendril::TendrilSink;
 use log_derive::logfn;
-use markup5ever_rcdom::Handle;
-use markup5ever_rcdom::NodeData;
-use markup5ever_rcdom::RcDom;
 
 use super::config::OrgConfig;
 use crate::storage::data::Data;
+use crate::storage::document::org;
+use crate::storage::document::Document;
+use crate::storage::errors::StorageError;
 use crate::storage::storage::Storage;
+use crate::storage::store::AttachmentStore;
+use crate::storage::store::StoredAttachment;
+use crate::storage::thread::ThreadMode;
 
 /// Definition for the Org storage
 pub struct Org {
@@ -44,96 +44,25 @@ pub struct Org {
     filename: String,
     /// The date being processed, needed for the header if it is a new file
     date: String,
+    /// How much of the conversation is saved
+    thread: ThreadMode,
+    /// Where the attachments are actually kept
+    store: Rc<AttachmentStore>,
 }
 
 /// Data used to dump the content into disk
 struct Dump<'a> {
     fp: File,
+    /// The entry being built, only written to the file when complete
+    entry: String,
     record: &'a Data,
     attachment_dir: PathBuf,
-}
-
-/// Simple macro to recursively walk through html5ever nodes
-macro_rules! keep_going {
-    ($source:ident, $target:ident) => {
-        for child in $source.children.borrow().iter() {
-            walk(child.borrow(), $target);
-        }
-    };
-}
-
-/// Walk though the html5ever nodes, producing the required string in Org
-/// format.
-fn walk(input: &Handle, result: &mut String) {
-    match input.data {
-        NodeData::Text { ref contents } => {
-            let text = contents.borrow().to_string();
-            result.push_str(&text);
-            keep_going!(input, result);
-        }
-        NodeData::Element {
-            ref name,
-            ref attrs,
-            ..
-        } => {
-            let tag = name.local.to_string();
-            match tag.as_ref() {
-                "html" | "head" | "body" => keep_going!(input, result),
-                "p" | "br" => {
-                    keep_going!(input, result);
-                    result.push_str("\n\n  ");
-                }
-                "span" => {
-                    let attrs = attrs.borrow();
-                    let classes_attr = attrs
-                        .iter()
-                        .find(|attr| attr.name.local.to_string() == "class");
-                    match classes_attr {
-                        Some(classes) => {
-                            if classes.value.contains("ellipsis") {
-                                keep_going!(input, result);
-                                result.push_str("...");
-                            } else if !classes.value.contains("invisible") {
-                                keep_going!(input, result);
-                            }
-                        }
-                        None => keep_going!(input, result),
-                    }
-                }
-                "a" => {
-                    let attrs = attrs.borrow();
-                    let rels = attrs
-                        .iter()
-                        .find(|attr| attr.name.local.to_string() == "rel");
-                    let hrefs = attrs
-                        .iter()
-                        .find(|attr| attr.name.local.to_string() == "href");
-                    match (rels, hrefs) {
-                        (Some(rel), Some(href)) => {
-                            if !rel.value.to_string().contains("tag") {
-                                result.push_str("[[");
-                                result.push_str(&href.value);
-                                result.push_str("][");
-                                keep_going!(input, result);
-                                result.push_str("]]");
-                            } else {
-                                keep_going!(input, result);
-                            }
-                        }
-                        _ => keep_going!(input, result),
-                    }
-                }
-                _ => {}
-            }
-        }
-        _ => {
-            keep_going!(input, result);
-        }
-    };
+    files: Vec<StoredAttachment<'a>>,
+    thread: ThreadMode,
 }
 
 impl Org {
-    pub(crate) fn new(config: &OrgConfig) -> Org {
+    pub(crate) fn new(config: &OrgConfig, store: Rc<AttachmentStore>) -> Org {
         let now = Utc::now();
         let filename = format!(
             "{:>04}{:>02}{:>02}.org",
@@ -150,140 +79,268 @@ impl Org {
             path: Path::new(&config.path).to_path_buf(),
             filename,
             date,
+            thread: config.thread,
+            store,
         }
     }
 
     #[logfn(Trace)]
-    fn attachment_dir(&self) -> PathBuf {
-        let attachment_dir = self.path.join(&self.date).to_path_buf();
-        std::fs::create_dir_all(&attachment_dir).unwrap();
-        attachment_dir
+    fn attachment_dir(&self) -> Result<PathBuf, StorageError> {
+        let attachment_dir = self.path.join(&self.date);
+        std::fs::create_dir_all(&attachment_dir)?;
+        Ok(attachment_dir)
     }
 
     /// Do the initialization of saving the data in Org format.
-    fn start_org<'a>(&self, record: &'a Data) -> Dump<'a> {
+    fn start_org<'a>(
+        &self,
+        record: &'a Data,
+    ) -> Result<Dump<'a>, StorageError> {
+        // Attachments are fetched before anything is written, so a failed
+        // download doesn't leave a partial entry in the file.
+        let files = self.store.files(&record.attachments)?;
         let org_file = self.path.join(&self.filename);
-        let fp = OpenOptions::new()
-            .write(true)
-            .append(true)
-            .open(&org_file)
-            .unwrap_or_else(|_| {
+        let fp = match OpenOptions::new().append(true).open(&org_file) {
+            Ok(fp) => fp,
+            Err(_) => {
                 // Let's assume here that the problem is that the file doesn't exist.
                 log::debug!(
                     "Creating {filename}",
                     filename = &org_file.to_string_lossy()
                 );
-                OpenOptions::new()
+                let mut fp = OpenOptions::new()
                     .write(true)
-                    .create(true)
-                    .open(&org_file)
-                    .map(|mut fp| {
-                        let text = format!(
-                            "#+title: Favourites from {date}\n\n",
-                            date = &self.date
-                        );
-                        fp.write_all(text.as_bytes()).unwrap();
-                        fp
-                    })
-                    .unwrap()
-            });
+                    .create_new(true)
+                    .open(&org_file)?;
+                let text = format!(
+                    "#+title: Favourites from {date}\n\n",
+                    date = &self.date
+                );
+                fp.write_all(text.as_bytes())?;
+                fp
+            }
+        };
 
-        Dump {
+        Ok(Dump {
             fp,
+            entry: String::new(),
             record,
-            attachment_dir: self.attachment_dir(),
-        }
+            attachment_dir: self.attachment_dir()?,
+            files,
+            thread: self.thread,
+        })
     }
 }
 
 impl Dump<'_> {
     /// The initial header for the content
-    fn intro(mut self) -> Self {
-        let title = format!(
+    fn intro(mut self) -> Result<Self, StorageError> {
+        let mut title = format!(
             "* {user}/{id}",
             user = &self.record.account,
             id = &self.record.id
         );
-        self.fp.write_all(title.as_bytes()).unwrap();
-        self.fp.write_all("\n".as_bytes()).unwrap();
-        self
+        if !self.record.tags.is_empty() {
+            title.push_str(&format!("  :{}:", self.record.tags.join(":")));
+        }
+        self.entry.push_str(&title);
+        self.entry.push('\n');
+        Ok(self)
+    }
+
+    /// The metadata of the content, as properties of the header
+    fn properties(mut self) -> Result<Self, StorageError> {
+        let record = self.record;
+        let mut properties = vec![
+            ("ID", record.id.to_string()),
+            ("ORIGIN", record.origin.name().to_string()),
+            ("AUTHOR", record.display_name.to_string()),
+            (
+                "CREATED",
+                record.created_at.format("[%Y-%m-%d %a %H:%M]").to_string(),
+            ),
+            ("VISIBILITY", record.visibility.to_string()),
+            (
+                "SENSITIVE",
+                if record.sensitive { "yes" } else { "no" }.into(),
+            ),
+        ];
+        if let Some(language) = &record.language {
+            properties.push(("LANGUAGE", language.to_string()));
+        }
+        if let Some(in_reply_to) = &record.in_reply_to {
+            properties.push(("IN_REPLY_TO", in_reply_to.to_string()));
+        }
+        if !record.mentions.is_empty() {
+            properties.push(("MENTIONS", record.mentions.join(" ")));
+        }
+        if !record.source.is_empty() {
+            properties.push(("SOURCE", record.source.to_string()));
+        }
+
+        self.entry.push_str("  :PROPERTIES:\n");
+        for (name, value) in properties {
+            let line = format!("  :{}: {}\n", name, value);
+            self.entry.push_str(&line);
+        }
+        self.entry.push_str("  :END:\n");
+        Ok(self)
     }
 
     /// If the content has a title (content warning), add it
-    fn title(mut self) -> Self {
+    fn title(mut self) -> Result<Self, StorageError> {
         if !self.record.title.is_empty() {
             let warning = format!("  ({})", &self.record.title);
-            self.fp.write_all(warning.as_bytes()).unwrap();
-            Dump::prologue(&mut self.fp);
+            self.entry.push_str(&warning);
+            Dump::prologue(&mut self.entry);
         }
-        self
+        Ok(self)
     }
 
     /// The main body of the content
-    fn text(mut self) -> Self {
-        let dom = parse_document(RcDom::default(), Default::default())
-            .from_utf8()
-            .read_from(&mut self.record.text.as_bytes())
-            .unwrap();
-        let mut result = String::new();
-        result.push_str("  "); // initial identantion
-        walk(&dom.document, &mut result);
-        self.fp.write_all("  ".as_bytes()).unwrap(); // initial indentantion
-        self.fp.write_all(result.trim().as_bytes()).unwrap();
-        Dump::prologue(&mut self.fp);
-        self
+    fn text(mut self) -> Result<Self, StorageError> {
+        let content = indent(&org::render(&Document::from(self.record)), "  ");
+        self.entry.push_str(&content);
+        Dump::prologue(&mut self.entry);
+        Ok(self)
     }
 
     /// Add the final attachments
-    fn attachments(mut self) -> Self {
-        if !self.record.attachments.is_empty() {
-            self.fp.write_all("  Attachments:\n".as_bytes()).unwrap();
-            for attachment in self.record.attachments.iter() {
-                let filename = attachment.filename().to_string();
-                let storage_name = format!("{}-{}", &self.record.id, &filename);
+    fn attachments(mut self) -> Result<Self, StorageError> {
+        if !self.files.is_empty() {
+            self.entry.push_str("  Attachments:\n");
+            for file in self.files.iter() {
+                let attachment = file.attachment;
+                let filename = &file.name;
+                let storage_name = format!("{}-{}", &self.record.id, filename);
                 let in_storage = self.attachment_dir.join(&storage_name);
                 log::debug!(
                     "Saving attachment in {}",
                     in_storage.to_string_lossy()
                 );
-                let mut target = File::create(&in_storage).unwrap();
-                log::debug!(
-                    "Downloading attachment {} as {}",
-                    filename,
-                    in_storage.to_string_lossy()
-                );
-                attachment.download().copy_to(&mut target).unwrap();
+                file.link(&in_storage)?;
+
+                self.entry.push('\n');
+                if let Some(description) = attachment.description() {
+                    let caption = format!(
+                        "  #+caption: {}\n",
+                        description.replace('\n', " ")
+                    );
+                    self.entry.push_str(&caption);
+                }
+                let attachment_info = match attachment.media_type() {
+                    "image" => {
+                        format!("  [[file:{}]]\n", in_storage.to_string_lossy())
+                    }
+                    _ => format!(
+                        "  [[file:{}][{}]]\n",
+                        in_storage.to_string_lossy(),
+                        filename
+                    ),
+                };
+                self.entry.push_str(&attachment_info);
+            }
+            Dump::prologue(&mut self.entry);
+        }
+        Ok(self)
+    }
+
+    /// The conversation around the favourite, as headings under it.
+    fn thread(mut self) -> Result<Self, StorageError> {
+        let record = self.record;
+        let sections = [
+            (
+                "In reply to",
+                &record.ancestors,
+                self.thread != ThreadMode::Off,
+            ),
+            (
+                "Replies",
+                &record.descendants,
+                self.thread.with_descendants(),
+            ),
+        ];
+        for (section, toots, enabled) in sections.iter() {
+            if !enabled || toots.is_empty() {
+                continue;
+            }
 
-                let attachment_info = format!(
-                    "  - [[file:{}][{}]\n",
-                    in_storage.to_string_lossy(),
-                    filename
+            self.entry.push_str(&format!("** {}\n", section));
+            for toot in toots.iter() {
+                let heading = format!(
+                    "*** {}/{}\n    :PROPERTIES:\n    :AUTHOR: {}\n    :CREATED: {}\n",
+                    toot.account,
+                    toot.id,
+                    toot.display_name,
+                    toot.created_at.format("[%Y-%m-%d %a %H:%M]")
                 );
-                self.fp.write_all(attachment_info.as_bytes()).unwrap();
+                self.entry.push_str(&heading);
+                if !toot.source.is_empty() {
+                    let source = format!("    :SOURCE: {}\n", toot.source);
+                    self.entry.push_str(&source);
+                }
+                self.entry.push_str("    :END:\n");
+
+                if !toot.title.is_empty() {
+                    let warning = format!("    ({})\n\n", toot.title);
+                    self.entry.push_str(&warning);
+                }
+                let content =
+                    indent(&org::render(&Document::from(toot)), "    ");
+                self.entry.push_str(&content);
+                // Attachments in the conversation are not downloaded, only
+                // linked.
+                for attachment in toot.attachments.iter() {
+                    let link = format!(
+                        "\n    [[{}][{}]]",
+                        attachment.url(),
+                        attachment.filename()
+                    );
+                    self.entry.push_str(&link);
+                }
+                Dump::prologue(&mut self.entry);
             }
-            Dump::prologue(&mut self.fp);
         }
-        self
+        Ok(self)
     }
 
     /// Prologue: The end of the content
-    fn prologue(fp: &mut File) {
-        fp.write_all("\n\n".as_bytes()).unwrap();
+    fn prologue(entry: &mut String) {
+        entry.push_str("\n\n");
     }
 
-    /// Done: Complete the data
-    fn done(self) {
-        // because we have all the prologues, we don't need to do anything else.
+    /// Done: Write the whole entry at once, so an error while building it
+    /// doesn't leave half an entry in the file.
+    fn done(mut self) -> Result<(), StorageError> {
+        self.fp.write_all(self.entry.as_bytes())?;
+        Ok(())
     }
 }
 
 impl Storage for Org {
-    fn save(&self, record: &Data) {
-        self.start_org(record)
-            .intro()
-            .title()
-            .text()
-            .attachments()
-            .done();
+    fn thread(&self) -> ThreadMode {
+        self.thread
+    }
+
+    fn save(&self, record: &Data) -> Result<(), StorageError> {
+        self.start_org(record)?
+            .intro()?
+            .properties()?
+            .title()?
+            .text()?
+            .attachments()?
+            .thread()?
+            .done()
     }
 }
+
+/// Indent every non-empty line of the text.
+fn indent(text: &str, indentation: &str) -> String {
+    text.lines()
+        .map(|line| match line {
+            "" => String::new(),
+            _ => format!("{}{}", indentation, line),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
@@ -0,0 +1,99 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use log_derive::logfn_inputs;
+
+use crate::storage::attachment::Attachment;
+use crate::storage::errors::StorageError;
+
+/// How many files are downloaded from the same server at the same time, if
+/// not defined in the configuration or the command line.
+pub const DEFAULT_PER_HOST: usize = 4;
+
+/// A download job: the attachment and where it should be saved.
+pub type Job<'a> = (&'a Attachment, PathBuf);
+
+/// Downloads files in parallel, limiting the number of simultaneous
+/// downloads from the same server.
+#[derive(Debug)]
+pub struct DownloadPool {
+    per_host: usize,
+}
+
+impl DownloadPool {
+    #[logfn_inputs(Trace)]
+    pub fn new(per_host: usize) -> Self {
+        Self {
+            per_host: per_host.max(1),
+        }
+    }
+
+    /// Download all the attachments, returning the result of each job -- the
+    /// content type of the file, if it worked -- in no particular order.
+    pub fn run<'a>(
+        &self,
+        jobs: Vec<Job<'a>>,
+    ) -> Vec<(Job<'a>, Result<Option<String>, StorageError>)> {
+        let mut hosts: HashMap<String, Vec<Job<'a>>> = HashMap::new();
+        for job in jobs.into_iter().rev() {
+            hosts.entry(host(job.0.url())).or_default().push(job);
+        }
+
+        let queues: Vec<(usize, Mutex<Vec<Job<'a>>>)> = hosts
+            .into_iter()
+            .map(|(host, queue)| {
+                let workers = queue.len().min(self.per_host);
+                log::debug!("{} downloads from {}", queue.len(), host);
+                (workers, Mutex::new(queue))
+            })
+            .collect();
+        let results = Mutex::new(Vec::new());
+        let finished = &results;
+        std::thread::scope(|scope| {
+            for (workers, queue) in queues.iter() {
+                for _ in 0..*workers {
+                    scope.spawn(move || loop {
+                        // The lock must be released before downloading, so
+                        // the other workers can pick their jobs.
+                        let next = queue.lock().unwrap().pop();
+                        match next {
+                            Some(job) => {
+                                let result = job.0.download(&job.1);
+                                finished.lock().unwrap().push((job, result));
+                            }
+                            None => break,
+                        }
+                    });
+                }
+            }
+        });
+        results.into_inner().unwrap()
+    }
+}
+
+/// The server of the URL, used to group the downloads.
+fn host(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(String::from))
+        .unwrap_or_default()
+}
@@ -0,0 +1,72 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::io::Write;
+
+use log_derive::logfn;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+
+use crate::config::errors::ConfigError;
+use crate::config::Configurable;
+
+/// Where the attachments are kept.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AttachmentStorage {
+    /// Inside the database, as blobs.
+    Database,
+    /// In a directory next to the database file.
+    Directory,
+}
+
+/// Configuration for the SQLite backend
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SqliteConfig {
+    /// The database file.
+    pub path: String,
+    /// Where the attachments are kept.
+    pub attachments: AttachmentStorage,
+}
+
+impl Configurable for SqliteConfig {
+    #[logfn(Trace)]
+    fn config() -> Result<Self, ConfigError> {
+        print!("Database file: ");
+        std::io::stdout().flush().expect("Failed to flush stdout!");
+
+        let mut path = String::new();
+        std::io::stdin().read_line(&mut path)?;
+        let fullpath = shellexpand::full(path.trim())?;
+
+        print!("Store attachments inside the database? [y/N] ");
+        std::io::stdout().flush().expect("Failed to flush stdout!");
+
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        let attachments = match answer.trim().to_lowercase().as_ref() {
+            "y" | "yes" => AttachmentStorage::Database,
+            _ => AttachmentStorage::Directory,
+        };
+
+        Ok(Self {
+            path: fullpath.into(),
+            attachments,
+        })
+    }
+}
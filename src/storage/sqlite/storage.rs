@@ -0,0 +1,355 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use elefren::entities::status::Status;
+use log_derive::logfn;
+use rusqlite::params;
+use rusqlite::Connection;
+
+use super::config::AttachmentStorage;
+use super::config::SqliteConfig;
+use crate::storage::data::Data;
+use crate::storage::errors::StorageError;
+use crate::storage::storage::Storage;
+use crate::storage::store::AttachmentStore;
+
+/// The database schema; every statement must be safe to run on an existing
+/// database.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS accounts (
+        acct TEXT PRIMARY KEY,
+        id TEXT NOT NULL,
+        username TEXT NOT NULL,
+        display_name TEXT NOT NULL,
+        url TEXT NOT NULL,
+        avatar TEXT NOT NULL,
+        bot INTEGER
+    );
+
+    CREATE TABLE IF NOT EXISTS statuses (
+        id TEXT PRIMARY KEY,
+        uri TEXT NOT NULL,
+        url TEXT,
+        account TEXT NOT NULL REFERENCES accounts(acct),
+        created_at TEXT NOT NULL,
+        in_reply_to_id TEXT,
+        spoiler_text TEXT NOT NULL,
+        content TEXT NOT NULL,
+        language TEXT,
+        visibility TEXT NOT NULL,
+        sensitive INTEGER NOT NULL,
+        reblogs_count INTEGER NOT NULL,
+        favourites_count INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS statuses_account ON statuses(account);
+    CREATE INDEX IF NOT EXISTS statuses_created_at ON statuses(created_at);
+
+    CREATE TABLE IF NOT EXISTS attachments (
+        id TEXT PRIMARY KEY,
+        status_id TEXT NOT NULL REFERENCES statuses(id) ON DELETE CASCADE,
+        media_type TEXT NOT NULL,
+        url TEXT NOT NULL,
+        remote_url TEXT,
+        preview_url TEXT NOT NULL,
+        description TEXT,
+        width INTEGER,
+        height INTEGER,
+        path TEXT,
+        sha256 TEXT,
+        content BLOB
+    );
+    CREATE INDEX IF NOT EXISTS attachments_status ON attachments(status_id);
+
+    CREATE TABLE IF NOT EXISTS origins (
+        status_id TEXT NOT NULL REFERENCES statuses(id) ON DELETE CASCADE,
+        origin TEXT NOT NULL,
+        PRIMARY KEY (status_id, origin)
+    );
+
+    CREATE TABLE IF NOT EXISTS tags (
+        status_id TEXT NOT NULL REFERENCES statuses(id) ON DELETE CASCADE,
+        name TEXT NOT NULL,
+        PRIMARY KEY (status_id, name)
+    );
+    CREATE INDEX IF NOT EXISTS tags_name ON tags(name);
+
+    CREATE TABLE IF NOT EXISTS mentions (
+        status_id TEXT NOT NULL REFERENCES statuses(id) ON DELETE CASCADE,
+        acct TEXT NOT NULL,
+        url TEXT NOT NULL,
+        PRIMARY KEY (status_id, acct)
+    );
+";
+
+/// Store favourites in a SQLite database.
+pub struct Sqlite {
+    connection: Connection,
+    path: PathBuf,
+    attachments: AttachmentStorage,
+    store: Rc<AttachmentStore>,
+}
+
+impl Storage for Sqlite {
+    fn save(&self, data: &Data) -> Result<(), StorageError> {
+        let transaction = self.connection.unchecked_transaction()?;
+        self.save_account(&data.status)?;
+        self.save_status(data)?;
+        self.save_origin(data)?;
+        self.save_tags(&data.status)?;
+        self.save_mentions(&data.status)?;
+        self.save_attachments(data)?;
+        transaction.commit()?;
+        println!("Saved to {}", self.path.to_string_lossy());
+        Ok(())
+    }
+}
+
+impl Sqlite {
+    pub fn new(
+        config: &SqliteConfig,
+        store: Rc<AttachmentStore>,
+    ) -> Result<Self, StorageError> {
+        let path = Path::new(&config.path).to_path_buf();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let connection = Connection::open(&path)?;
+        connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+        connection.execute_batch(SCHEMA)?;
+        Sqlite::add_column(&connection, "attachments", "width", "INTEGER")?;
+        Sqlite::add_column(&connection, "attachments", "height", "INTEGER")?;
+        Sqlite::add_column(&connection, "attachments", "sha256", "TEXT")?;
+        Ok(Self {
+            connection,
+            path,
+            attachments: config.attachments,
+            store,
+        })
+    }
+
+    /// Add a column to a table created by an older version of the schema.
+    fn add_column(
+        connection: &Connection,
+        table: &str,
+        column: &str,
+        definition: &str,
+    ) -> Result<(), StorageError> {
+        let mut statement =
+            connection.prepare(&format!("PRAGMA table_info({})", table))?;
+        let columns = statement
+            .query_map(params![], |row| row.get::<_, String>(1))?
+            .collect::<Result<Vec<String>, _>>()?;
+        if !columns.iter().any(|name| name == column) {
+            log::debug!("Adding {}.{}", table, column);
+            connection.execute_batch(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {};",
+                table, column, definition
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// The directory for the attachments, when they are not kept in the
+    /// database: `<database name>-attachments`, next to the database file.
+    #[logfn(Trace)]
+    fn attachment_dir(&self, data: &Data) -> PathBuf {
+        let stem = self
+            .path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "downfav".into());
+        self.path
+            .with_file_name(format!("{}-attachments", stem))
+            .join(&data.id)
+    }
+
+    fn save_account(&self, status: &Status) -> Result<(), StorageError> {
+        let account = &status.account;
+        self.connection.execute(
+            "INSERT INTO accounts
+                (acct, id, username, display_name, url, avatar, bot)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(acct) DO UPDATE SET
+                id = excluded.id,
+                username = excluded.username,
+                display_name = excluded.display_name,
+                url = excluded.url,
+                avatar = excluded.avatar,
+                bot = excluded.bot",
+            params![
+                account.acct,
+                account.id,
+                account.username,
+                account.display_name,
+                account.url,
+                account.avatar,
+                account.bot,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn save_status(&self, data: &Data) -> Result<(), StorageError> {
+        let status = &data.status;
+        self.connection.execute(
+            "INSERT INTO statuses
+                (id, uri, url, account, created_at, in_reply_to_id,
+                 spoiler_text, content, language, visibility, sensitive,
+                 reblogs_count, favourites_count)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+             ON CONFLICT(id) DO UPDATE SET
+                uri = excluded.uri,
+                url = excluded.url,
+                account = excluded.account,
+                created_at = excluded.created_at,
+                in_reply_to_id = excluded.in_reply_to_id,
+                spoiler_text = excluded.spoiler_text,
+                content = excluded.content,
+                language = excluded.language,
+                visibility = excluded.visibility,
+                sensitive = excluded.sensitive,
+                reblogs_count = excluded.reblogs_count,
+                favourites_count = excluded.favourites_count",
+            params![
+                status.id,
+                status.uri,
+                status.url,
+                status.account.acct,
+                status.created_at.to_rfc3339(),
+                status.in_reply_to_id,
+                status.spoiler_text,
+                status.content,
+                status.language,
+                data.visibility,
+                status.sensitive,
+                status.reblogs_count as i64,
+                status.favourites_count as i64,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Record why the toot was archived; a toot can be both a favourite and
+    /// a bookmark.
+    fn save_origin(&self, data: &Data) -> Result<(), StorageError> {
+        self.connection.execute(
+            "INSERT OR IGNORE INTO origins (status_id, origin) VALUES (?1, ?2)",
+            params![data.id, data.origin.name()],
+        )?;
+        Ok(())
+    }
+
+    fn save_tags(&self, status: &Status) -> Result<(), StorageError> {
+        self.connection.execute(
+            "DELETE FROM tags WHERE status_id = ?1",
+            params![status.id],
+        )?;
+        for tag in status.tags.iter() {
+            self.connection.execute(
+                "INSERT OR IGNORE INTO tags (status_id, name) VALUES (?1, ?2)",
+                params![status.id, tag.name.to_lowercase()],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn save_mentions(&self, status: &Status) -> Result<(), StorageError> {
+        self.connection.execute(
+            "DELETE FROM mentions WHERE status_id = ?1",
+            params![status.id],
+        )?;
+        for mention in status.mentions.iter() {
+            self.connection.execute(
+                "INSERT OR IGNORE INTO mentions (status_id, acct, url)
+                 VALUES (?1, ?2, ?3)",
+                params![status.id, mention.acct, mention.url],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn save_attachments(&self, data: &Data) -> Result<(), StorageError> {
+        let media = data.status.media_attachments.iter();
+        let files = self.store.files(&data.attachments)?;
+        // The status may have been edited to remove some of the attachments.
+        self.connection.execute(
+            "DELETE FROM attachments WHERE status_id = ?1",
+            params![data.id],
+        )?;
+        for (origin, file) in media.zip(files.iter()) {
+            let attachment = file.attachment;
+            let (width, height) = match attachment.dimensions() {
+                Some((width, height)) => {
+                    (Some(width as i64), Some(height as i64))
+                }
+                None => (None, None),
+            };
+            let (path, content) = match self.attachments {
+                AttachmentStorage::Database => {
+                    (None, Some(std::fs::read(&file.path)?))
+                }
+                AttachmentStorage::Directory => {
+                    let dir = self.attachment_dir(data);
+                    std::fs::create_dir_all(&dir)?;
+                    let filename = dir.join(&file.name);
+                    file.link(&filename)?;
+                    (Some(filename.to_string_lossy().to_string()), None)
+                }
+            };
+
+            self.connection.execute(
+                "INSERT INTO attachments
+                    (id, status_id, media_type, url, remote_url, preview_url,
+                     description, width, height, path, sha256, content)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+                 ON CONFLICT(id) DO UPDATE SET
+                    status_id = excluded.status_id,
+                    media_type = excluded.media_type,
+                    url = excluded.url,
+                    remote_url = excluded.remote_url,
+                    preview_url = excluded.preview_url,
+                    description = excluded.description,
+                    width = excluded.width,
+                    height = excluded.height,
+                    path = excluded.path,
+                    sha256 = excluded.sha256,
+                    content = excluded.content",
+                params![
+                    origin.id,
+                    data.id,
+                    attachment.media_type(),
+                    attachment.url(),
+                    attachment.remote_url(),
+                    attachment.preview_url(),
+                    attachment.description(),
+                    width,
+                    height,
+                    path,
+                    file.hash(),
+                    content,
+                ],
+            )?;
+        }
+        Ok(())
+    }
+}
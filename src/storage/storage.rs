@@ -17,15 +17,26 @@
 */
 
 use crate::storage::data::Data;
+use crate::storage::errors::StorageError;
+use crate::storage::thread::ThreadMode;
 
 /// Trait for storing favorites on a storage.
 pub trait Storage {
     /// Storage initialization
-    fn init(&self) {}
+    fn init(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    /// How much of the conversation of each favourite the storage wants.
+    fn thread(&self) -> ThreadMode {
+        ThreadMode::Off
+    }
 
     /// Save the favourite in the storage.
-    fn save(&self, record: &Data);
+    fn save(&self, record: &Data) -> Result<(), StorageError>;
 
     /// Storage finalization
-    fn close(&self) {}
+    fn close(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
 }
@@ -0,0 +1,370 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use directories::ProjectDirs;
+use log_derive::logfn_inputs;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::storage::attachment::Attachment;
+use crate::storage::errors::StorageError;
+use crate::storage::filename::disambiguate;
+use crate::storage::filename::extension_for;
+use crate::storage::filename::with_extension;
+use crate::storage::pool::DownloadPool;
+
+/// Attachments, shared by all storages, stored by the hash of their content.
+///
+/// Files are kept in `<store>/ab/abcdef....ext`, where `abcdef...` is the
+/// SHA-256 of the content; an index, in the same directory, maps the URLs
+/// already downloaded to their files, so the same URL is never downloaded
+/// twice, and the same content is never stored twice, even if it comes from
+/// different URLs.
+#[derive(Debug)]
+pub struct AttachmentStore {
+    root: PathBuf,
+    index: RefCell<HashMap<String, PathBuf>>,
+    pool: DownloadPool,
+    /// Downloads that failed while prefetching, so they are reported by the
+    /// storage that needs them.
+    failed: RefCell<HashMap<String, StorageError>>,
+}
+
+impl AttachmentStore {
+    /// Open the store in the user data directory, downloading up to
+    /// `per_host` files at the same time from the same server.
+    ///
+    /// The store is shared by every configuration: it stays in the data
+    /// directory even when another configuration file is used (`--config`).
+    #[logfn_inputs(Trace)]
+    pub fn open(per_host: usize) -> Result<Self, StorageError> {
+        let root = match ProjectDirs::from("me", "JulioBiason", "downfav") {
+            Some(proj_dirs) => proj_dirs.data_dir().join("attachments"),
+            None => PathBuf::from("attachments"),
+        };
+        AttachmentStore::open_at(&root, per_host)
+    }
+
+    /// Open the store in a specific directory.
+    #[logfn_inputs(Trace)]
+    pub fn open_at(root: &Path, per_host: usize) -> Result<Self, StorageError> {
+        std::fs::create_dir_all(root)?;
+        let mut index = HashMap::new();
+        if let Ok(fp) = File::open(root.join("index")) {
+            for line in BufReader::new(fp).lines() {
+                let line = line?;
+                let mut fields = line.splitn(2, '\t');
+                if let (Some(file), Some(url)) = (fields.next(), fields.next())
+                {
+                    index.insert(url.to_string(), root.join(file));
+                }
+            }
+        }
+        Ok(Self {
+            root: root.into(),
+            index: RefCell::new(index),
+            pool: DownloadPool::new(per_host),
+            failed: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Download, in parallel, the attachments that are not in the store yet.
+    ///
+    /// Failures are not reported here, but by the next `fetch` (or `link`)
+    /// of the attachment.
+    pub fn prefetch(&self, attachments: &[Attachment]) {
+        let mut jobs = Vec::new();
+        for attachment in attachments.iter() {
+            if self.stored(attachment).is_some()
+                || jobs.iter().any(|(queued, _): &(&Attachment, PathBuf)| {
+                    queued.url() == attachment.url()
+                })
+            {
+                continue;
+            }
+            match self.download_path(attachment) {
+                Ok(path) => jobs.push((attachment, path)),
+                Err(e) => {
+                    self.failed.borrow_mut().insert(attachment.url().into(), e);
+                }
+            }
+        }
+        if jobs.is_empty() {
+            return;
+        }
+
+        for ((attachment, downloaded), result) in self.pool.run(jobs) {
+            if let Err(e) = result.and_then(|content_type| {
+                self.add(attachment, &downloaded, content_type.as_deref())
+            }) {
+                log::debug!("Failed to prefetch {}: {:?}", attachment.url(), e);
+                self.failed.borrow_mut().insert(attachment.url().into(), e);
+            }
+        }
+    }
+
+    /// The file with the attachment content in the store, downloading it if
+    /// this is the first time we see it.
+    pub fn fetch(
+        &self,
+        attachment: &Attachment,
+    ) -> Result<PathBuf, StorageError> {
+        if let Some(path) = self.stored(attachment) {
+            log::debug!("{} already in the store", attachment.url());
+            return Ok(path);
+        }
+        if let Some(e) = self.failed.borrow_mut().remove(attachment.url()) {
+            return Err(e);
+        }
+
+        let downloaded = self.download_path(attachment)?;
+        let content_type = attachment.download(&downloaded)?;
+        self.add(attachment, &downloaded, content_type.as_deref())
+    }
+
+    /// Add an attachment whose content we already have (e.g., from an export
+    /// archive), so it is never downloaded.
+    pub fn import(
+        &self,
+        attachment: &Attachment,
+        content: &mut impl Read,
+    ) -> Result<PathBuf, StorageError> {
+        if let Some(path) = self.stored(attachment) {
+            log::debug!("{} already in the store", attachment.url());
+            return Ok(path);
+        }
+
+        let copied = self.download_path(attachment)?;
+        std::io::copy(content, &mut File::create(&copied)?)?;
+        self.add(attachment, &copied, None)
+    }
+
+    /// Fetch all the attachments of a toot, naming each one so they can be
+    /// saved together in the same directory.
+    pub fn files<'a>(
+        &self,
+        attachments: &'a [Attachment],
+    ) -> Result<Vec<StoredAttachment<'a>>, StorageError> {
+        let mut paths = Vec::new();
+        for attachment in attachments.iter() {
+            paths.push(self.fetch(attachment)?);
+        }
+        let names = disambiguate(
+            attachments
+                .iter()
+                .zip(paths.iter())
+                .map(|(attachment, path)| {
+                    with_extension(
+                        &attachment.filename(),
+                        path.extension()
+                            .and_then(|extension| extension.to_str()),
+                    )
+                })
+                .collect(),
+        );
+        Ok(attachments
+            .iter()
+            .zip(paths)
+            .zip(names)
+            .map(|((attachment, path), name)| StoredAttachment {
+                attachment,
+                path,
+                name,
+            })
+            .collect())
+    }
+
+    /// The file with the attachment content, if it is already in the store.
+    fn stored(&self, attachment: &Attachment) -> Option<PathBuf> {
+        self.index
+            .borrow()
+            .get(attachment.url())
+            .filter(|path| path.is_file())
+            .cloned()
+    }
+
+    /// Where the attachment is downloaded to, before being moved to the
+    /// store. Downloads are named after the URL, so an interrupted one can be
+    /// resumed in the next run.
+    fn download_path(
+        &self,
+        attachment: &Attachment,
+    ) -> Result<PathBuf, StorageError> {
+        let downloads = self.root.join("downloads");
+        std::fs::create_dir_all(&downloads)?;
+        Ok(downloads.join(sha256(attachment.url().as_bytes())))
+    }
+
+    /// Move a downloaded file to the store, indexing its URL. The extension of
+    /// the file comes from the URL or, if it has none, from the content type.
+    fn add(
+        &self,
+        attachment: &Attachment,
+        downloaded: &Path,
+        content_type: Option<&str>,
+    ) -> Result<PathBuf, StorageError> {
+        let hash = AttachmentStore::hash_file(downloaded)?;
+        let filename = with_extension(
+            &attachment.filename(),
+            content_type.and_then(extension_for),
+        );
+        let name = match Path::new(&filename).extension() {
+            Some(extension) => {
+                format!("{}.{}", hash, extension.to_string_lossy())
+            }
+            None => hash.to_string(),
+        };
+        let relative = Path::new(&hash[..2]).join(name);
+        let path = self.root.join(&relative);
+        if path.is_file() {
+            log::debug!("Content of {} already in the store", attachment.url());
+            std::fs::remove_file(downloaded)?;
+        } else {
+            std::fs::create_dir_all(self.root.join(&hash[..2]))?;
+            std::fs::rename(downloaded, &path)?;
+        }
+
+        let mut index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.root.join("index"))?;
+        writeln!(
+            index,
+            "{}\t{}",
+            relative.to_string_lossy(),
+            attachment.url()
+        )?;
+        self.index
+            .borrow_mut()
+            .insert(attachment.url().into(), path.clone());
+        Ok(path)
+    }
+
+    /// The SHA-256 of the content of a file.
+    fn hash_file(path: &Path) -> Result<String, StorageError> {
+        let mut fp = File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut buffer = [0; 64 * 1024];
+        loop {
+            let read = fp.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+        Ok(hex(&hasher.finalize()))
+    }
+}
+
+/// An attachment of a toot, in the store.
+#[derive(Debug)]
+pub struct StoredAttachment<'a> {
+    pub attachment: &'a Attachment,
+    /// The file in the store.
+    pub path: PathBuf,
+    /// The name for the file in the toot: safe for any filesystem, with an
+    /// extension (when one is known) and unique among the toot attachments.
+    pub name: String,
+}
+
+impl StoredAttachment<'_> {
+    /// The content hash of the file.
+    pub fn hash(&self) -> String {
+        self.path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    /// Make the attachment available in the target path, as a hard link to
+    /// the file in the store; if that's not possible (e.g., the target is in
+    /// another filesystem), use a relative symbolic link or, as a last
+    /// resort, a copy.
+    pub fn link(&self, target: &Path) -> Result<(), StorageError> {
+        if target.symlink_metadata().is_ok() {
+            std::fs::remove_file(target)?;
+        }
+
+        if let Err(e) = std::fs::hard_link(&self.path, target) {
+            log::debug!("Can't hard link {:?}: {:?}", target, e);
+            if symlink(&self.path, target).is_err() {
+                std::fs::copy(&self.path, target)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The SHA-256 of some content, in hex.
+pub fn sha256(content: &[u8]) -> String {
+    hex(&Sha256::digest(content))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Create a symbolic link to the source, relative to the target directory.
+#[cfg(unix)]
+fn symlink(source: &Path, target: &Path) -> std::io::Result<()> {
+    let source = source.canonicalize()?;
+    let directory = match target.parent() {
+        Some(parent) if parent != Path::new("") => parent.canonicalize()?,
+        _ => std::env::current_dir()?,
+    };
+    std::os::unix::fs::symlink(relative_path(&directory, &source), target)
+}
+
+#[cfg(not(unix))]
+fn symlink(_source: &Path, _target: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "symbolic links are not supported",
+    ))
+}
+
+/// The path to `to`, relative to the `from` directory; both must be absolute.
+#[cfg(unix)]
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+    let common = from
+        .iter()
+        .zip(to.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut result = PathBuf::new();
+    for _ in common..from.len() {
+        result.push("..");
+    }
+    for component in to[common..].iter() {
+        result.push(component.as_os_str());
+    }
+    result
+}
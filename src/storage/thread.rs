@@ -0,0 +1,59 @@
+/*
+   DOWNFAV - Download Favourites
+   Copyright (C) 2020-2021  Julio Biason
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU Affero General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU Affero General Public License for more details.
+
+   You should have received a copy of the GNU Affero General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::io::Write;
+
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+
+use crate::config::errors::ConfigError;
+
+/// How much of the conversation around a favourite a storage keeps.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ThreadMode {
+    /// Only the favourite itself.
+    #[default]
+    Off,
+    /// The favourite and the toots it replies to.
+    Ancestors,
+    /// The favourite, the toots it replies to and the replies to it.
+    Full,
+}
+
+impl ThreadMode {
+    /// Ask, when configuring a storage, how much of the conversation should
+    /// be kept.
+    pub fn ask() -> Result<Self, ConfigError> {
+        print!("Save the conversation of each favourite? [n]o, [a]ncestors, [f]ull thread: ");
+        std::io::stdout().flush().expect("Failed to flush stdout!");
+
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        Ok(match answer.trim().to_lowercase().as_ref() {
+            "a" | "ancestors" => ThreadMode::Ancestors,
+            "f" | "full" => ThreadMode::Full,
+            _ => ThreadMode::Off,
+        })
+    }
+
+    /// Whether the replies to the favourite should be kept.
+    pub fn with_descendants(&self) -> bool {
+        *self == ThreadMode::Full
+    }
+}